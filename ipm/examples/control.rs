@@ -0,0 +1,67 @@
+use flexi_logger::Logger;
+use log::info;
+use tonic::Request;
+
+use control::control_client::ControlClient;
+use control::{Empty, Instruments};
+use stats::stats_client::StatsClient;
+
+pub mod control {
+    tonic::include_proto!("control");
+}
+
+pub mod stats {
+    tonic::include_proto!("stats");
+}
+
+const URL: &str = "https://[::1]:10000";
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    Logger::try_with_str("info")?
+        .format(flexi_logger::colored_detailed_format)
+        .start()?;
+
+    // Первый аргумент - команда (subscribe, unsubscribe, list, connections, channels, outbox), остальные - инструменты
+    let mut args = std::env::args().skip(1);
+    let command = args.next().unwrap_or_else(|| "list".to_string());
+    let figis: Vec<String> = args.collect();
+
+    if command == "channels" {
+        let mut client = StatsClient::connect(URL).await?;
+        let response = client.get_channels(Request::new(stats::Empty {})).await?;
+        for channel in response.into_inner().channels {
+            info!("channel: {:?}", channel);
+        }
+
+        return Ok(());
+    }
+
+    let mut client = ControlClient::connect(URL).await?;
+
+    if command == "outbox" {
+        let response = client.get_outbox(Request::new(Empty {})).await?;
+        info!("outbox: {:?}", response.into_inner());
+
+        return Ok(());
+    }
+
+    if command == "connections" {
+        let response = client.get_connections(Request::new(Empty {})).await?;
+        for connection in response.into_inner().connections {
+            info!("connection: {:?}", connection);
+        }
+
+        return Ok(());
+    }
+
+    let response = match command.as_str() {
+        "subscribe" => client.subscribe(Request::new(Instruments { figis })).await?,
+        "unsubscribe" => client.unsubscribe(Request::new(Instruments { figis })).await?,
+        _ => client.get_instruments(Request::new(Empty {})).await?,
+    };
+
+    info!("instruments: {:?}", response.into_inner().figis);
+
+    Ok(())
+}
@@ -2,12 +2,12 @@ use std::error::Error;
 
 use chrono::Utc;
 use flexi_logger::Logger;
-use log::info;
+use log::{info, warn};
 use tonic::transport::Channel;
 use tonic::Request;
 
 use incoming::price_stream_client::PriceStreamClient;
-use incoming::Empty;
+use incoming::Subscription;
 
 pub mod incoming {
     tonic::include_proto!("incoming");
@@ -23,18 +23,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let mut client = PriceStreamClient::connect(URL).await?;
     info!("grpc client started");
-    get_order_book(&mut client).await?;
+
+    // Инструменты для подписки берем из аргументов командной строки, если их нет - подписываемся на все
+    let figis: Vec<String> = std::env::args().skip(1).collect();
+    get_order_book(&mut client, figis).await?;
 
     Ok(())
 }
 
-async fn get_order_book(client: &mut PriceStreamClient<Channel>) -> Result<(), Box<dyn Error>> {
+async fn get_order_book(client: &mut PriceStreamClient<Channel>, figis: Vec<String>) -> Result<(), Box<dyn Error>> {
     let mut stream = client
-        .subscribe_to_order_book(Request::new(Empty {}))
+        .subscribe_to_order_book(Request::new(Subscription { figis }))
         .await?
         .into_inner();
 
     while let Some(msg) = stream.message().await? {
+        if msg.dropped > 0 {
+            warn!("gap marker, dropped messages: {}", msg.dropped);
+            continue;
+        }
+
         let now = Utc::now().timestamp_millis();
         let diff = now - msg.received; // "транспортные расходы" - время затраченное на передачу данных от ipm сюда
 
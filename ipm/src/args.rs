@@ -4,6 +4,9 @@ use std::ops::Deref;
 const CONFIGS: &str = "configs";
 const WS_EMULATE: &str = "ws_emulate";
 const REPOSITORY: &str = "repository";
+const WS_REPLAY: &str = "ws_replay";
+const REPLAY_SPEED: &str = "replay_speed";
+const SCENARIO: &str = "scenario";
 
 pub struct Args(ArgMatches);
 
@@ -35,6 +38,30 @@ impl Args {
                     .takes_value(false)
                     .about("sets a to repository sending mode"),
             )
+            .arg(
+                Arg::new(SCENARIO)
+                    .short('n')
+                    .long(SCENARIO)
+                    .value_name("PATH TO SCENARIO")
+                    .requires(WS_EMULATE)
+                    .about("sets a scenario file (yaml or json) for the ws emulate mode"),
+            )
+            .arg(
+                Arg::new(WS_REPLAY)
+                    .short('p')
+                    .long(WS_REPLAY)
+                    .value_name("PATH TO RECORDS")
+                    .conflicts_with(WS_EMULATE)
+                    .about("replays recorded ws frames (file or directory) instead of connecting to tinkoff"),
+            )
+            .arg(
+                Arg::new(REPLAY_SPEED)
+                    .short('s')
+                    .long(REPLAY_SPEED)
+                    .value_name("SPEED")
+                    .default_value("1")
+                    .about("sets a replay speed: 1 - as recorded, N - N times faster, max - without pauses"),
+            )
             .get_matches();
 
         Args(am)
@@ -48,6 +75,18 @@ impl Args {
         self.is_present(WS_EMULATE)
     }
 
+    pub fn get_scenario_path(&self) -> Option<&str> {
+        self.value_of(SCENARIO)
+    }
+
+    pub fn get_replay_path(&self) -> Option<&str> {
+        self.value_of(WS_REPLAY)
+    }
+
+    pub fn get_replay_speed(&self) -> Option<&str> {
+        self.value_of(REPLAY_SPEED)
+    }
+
     pub fn is_repository(&self) -> bool {
         self.is_present(REPOSITORY)
     }
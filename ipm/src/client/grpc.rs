@@ -1,12 +1,16 @@
 use anyhow::anyhow;
-use log::{error, info};
-use tokio::sync::broadcast;
+use log::{info, warn};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
 use tokio_util::sync::CancellationToken;
 use tonic::transport::Channel;
 use tonic::Request;
 
-use incoming::{OrderBook, Trade};
 use repository::price_storage_client::PriceStorageClient;
+use repository::{envelope, Ack, Code, Envelope};
+
+use crate::client::outbox::{Item, Outbox, Record};
+use crate::client::reconnect::ConnectionMonitor;
 
 // В repository есть заимствованные структуры (message) из incoming (Trade и OrderBook), поэтому incoming тоже нужно подключать
 pub mod incoming {
@@ -19,92 +23,103 @@ pub mod repository {
 
 trade_from!(incoming);
 order_book_from!(incoming);
+candle_from!(incoming);
 
+/// source - имя источника данных для pr, вместе с идентификатором очереди (outbox) определяет нумерацию сообщений
 pub async fn run(
     addr: String,
-    trade_receiver: broadcast::Receiver<DomainTrade>,
-    order_book_receiver: broadcast::Receiver<DomainOrderBook>,
+    source: String,
+    monitor: ConnectionMonitor,
+    outbox: Outbox,
     shutdown: CancellationToken,
 ) -> anyhow::Result<()> {
     let url = format!("https://{}", addr);
     let client = PriceStorageClient::connect(url).await?;
     info!("price repository connected");
-    sending(client, trade_receiver, order_book_receiver, shutdown).await
+    monitor.connected();
+    sending(client, source, outbox, shutdown).await
 }
 
+/// Данные берутся из очереди на диске (client::outbox) и отправляются в pr через один двунаправленный поток (Deliver):
+/// в одну сторону идут сообщения с номерами, в обратную - подтверждения сохранения в базу, без ожидания подтверждения
+/// на каждое сообщение. Подтвержденные сообщения подтверждаются и в очереди, отклоненные pr (INVALID_PAYLOAD,
+/// UNKNOWN_FIGI) тоже, повторно их не отправляем. Временная ошибка pr (QUEUE_FULL, STORAGE_ERROR) или неизвестный
+/// статус приводит к переподключению и повторной отправке с этого сообщения.
+/// Поток завершается без ошибки только по сигналу на завершение (после того, как pr подтвердит все отправленное),
+/// ошибка приводит к переподключению (start_and_restart_grpc_client), после переподключения отправка продолжается
+/// с последнего подтвержденного сообщения, повторно отправленные сообщения pr отбрасывает как дубликаты.
 async fn sending(
     mut client: PriceStorageClient<Channel>,
-    mut trade_receiver: broadcast::Receiver<DomainTrade>,
-    mut order_book_receiver: broadcast::Receiver<DomainOrderBook>,
+    source: String,
+    outbox: Outbox,
     shutdown: CancellationToken,
 ) -> anyhow::Result<()> {
-    let result = tokio::spawn(async move {
-        loop {
-            let result: Option<anyhow::Result<()>> = tokio::select! {
-                val = trade_receiver.recv() => {
-                    match val {
-                        Ok(trade) => {
-                            send_trade(&mut client, trade).await
-                        },
-                        Err(err) => {
-                            error!("unexpected error: {:?}", err);
-                            None
-                        }
-                    }
-                },
-                val = order_book_receiver.recv() => {
-                    match val {
-                        Ok(order_book) => {
-                            send_order_book(&mut client, order_book).await
-                        },
-                        Err(err) => {
-                            error!("unexpected error: {:?}", err);
-                            None
-                        }
-                    }
-                },
-                _ = shutdown.cancelled() => {
-                    info!("grpc client finished");
-                    Some(anyhow::Result::Ok(()))
-                }
+    let source = format!("{}/{}", source, outbox.id());
+    let (tx, envelopes) = mpsc::channel::<Envelope>(1);
+
+    outbox.rewind().await;
+    let mut acks = client
+        .deliver(Request::new(ReceiverStream::new(envelopes)))
+        .await?
+        .into_inner();
+
+    let (o, s) = (outbox.clone(), shutdown.clone());
+    let dispatching = async move {
+        while let Some(Record { sequence, item }) = o.next(&s).await? {
+            let item = match item {
+                Item::Trade(trade) => envelope::Item::Trade(trade),
+                Item::OrderBook(order_book) => envelope::Item::OrderBook(order_book),
+                Item::Candle(candle) => envelope::Item::Candle(candle),
             };
 
-            if let Some(res) = result {
-                return res;
+            let envelope = Envelope {
+                source: source.clone(),
+                sequence,
+                item: Some(item),
+            };
+
+            if tx.send(envelope).await.is_err() {
+                return Err(anyhow!("outgoing stream closed"));
             }
         }
-    })
-    .await
-    .unwrap();
 
-    result
-}
+        // По сигналу на завершение сендер закрывается, вместе с ним закрывается поток, pr подтверждает оставшееся
+        Ok::<_, anyhow::Error>(())
+    };
 
-async fn send_trade(client: &mut PriceStorageClient<Channel>, trade: DomainTrade) -> Option<anyhow::Result<()>> {
-    let t = Trade::from(trade);
-    let request = Request::new(t);
-    let response = client.add_trade(request).await;
-    match response {
-        Ok(_) => None,
-        Err(err) => {
-            error!("trade sending error: {:?}", err);
-            Some(Err(anyhow!("streaming error: {:?}", err))) // Выходим с ошибкой, это приведет к переподключению (start_and_restart_grpc_client)
+    let acking = async move {
+        while let Some(Ack {
+            sequence,
+            status,
+            message,
+        }) = acks.message().await?
+        {
+            match Code::from_i32(status) {
+                Some(Code::Accepted) => outbox.commit(sequence).await,
+                Some(Code::InvalidPayload) | Some(Code::UnknownFigi) => {
+                    warn!("message {} is rejected by price repository: {}", sequence, message);
+                    outbox.reject(sequence).await;
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "price repository could not store message {} (status {}): {}",
+                        sequence,
+                        status,
+                        message
+                    ))
+                }
+            }
         }
-    }
-}
 
-async fn send_order_book(
-    client: &mut PriceStorageClient<Channel>,
-    order_book: DomainOrderBook,
-) -> Option<anyhow::Result<()>> {
-    let ob = OrderBook::from(order_book);
-    let request = tonic::Request::new(ob);
-    let response = client.add_order_book(request).await;
-    match response {
-        Ok(_) => None,
-        Err(err) => {
-            error!("order book sending error: {:?}", err);
-            Some(Err(anyhow!("streaming error: {:?}", err)))
+        // pr может завершить поток без ошибки только после того, как клиент закрыл свою сторону
+        if !shutdown.is_cancelled() {
+            return Err(anyhow!("stream closed by price repository"));
         }
-    }
+        Ok(())
+    };
+
+    tokio::try_join!(dispatching, acking)?;
+
+    info!("grpc client finished");
+    Ok(())
 }
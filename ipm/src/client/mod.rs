@@ -1,2 +1,4 @@
 pub mod grpc;
+pub mod outbox;
+pub mod reconnect;
 pub mod ws;
@@ -0,0 +1,603 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{error, info, warn};
+use prost::Message;
+use tokio::sync::{mpsc, oneshot, Notify};
+use tokio::time;
+use tokio_util::sync::CancellationToken;
+
+use super::grpc::incoming::{Candle, OrderBook, Trade};
+use crate::domain::{
+    candle::Candle as DomainCandle, order_book::OrderBook as DomainOrderBook, trade::Trade as DomainTrade,
+};
+use crate::settings::Outbox as OutboxCfg;
+use shared::channel::Receiver;
+
+const SEGMENT_PREFIX: &str = "outbox-";
+const SEGMENT_EXT: &str = "bin";
+const POSITION_FILE: &str = "position";
+const ID_FILE: &str = "id";
+const HEADER_LEN: u64 = 13; // тип (1 байт) + номер (8 байт) + длина (4 байта)
+const MB: u64 = 1024 * 1024;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+const COMMANDS_CAPACITY: usize = 10_000;
+
+/// Сообщение для pr в том виде, в котором оно отправляется (protobuf)
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    Trade(Trade),
+    OrderBook(OrderBook),
+    Candle(Candle),
+}
+
+impl Item {
+    fn encode(&self) -> (u8, Vec<u8>) {
+        match self {
+            Item::Trade(trade) => (1, trade.encode_to_vec()),
+            Item::OrderBook(order_book) => (2, order_book.encode_to_vec()),
+            Item::Candle(candle) => (3, candle.encode_to_vec()),
+        }
+    }
+
+    fn decode(kind: u8, data: &[u8]) -> io::Result<Self> {
+        let invalid = |err| io::Error::new(ErrorKind::InvalidData, err);
+        match kind {
+            1 => Trade::decode(data).map(Item::Trade).map_err(invalid),
+            2 => OrderBook::decode(data).map(Item::OrderBook).map_err(invalid),
+            3 => Candle::decode(data).map(Item::Candle).map_err(invalid),
+            _ => Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown item kind: {}", kind),
+            )),
+        }
+    }
+}
+
+/// Сообщение из очереди с его номером: номера возрастают и не повторяются в рамках очереди (в том числе после
+/// перезапуска ipm), по ним pr подтверждает сохранение и отбрасывает дубликаты
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub sequence: u64,
+    pub item: Item,
+}
+
+/// Состояние очереди и счетчики с момента запуска (pending и size_bytes - с учетом данных, оставшихся с прошлого запуска)
+#[derive(Debug, Clone, Default)]
+pub struct OutboxStats {
+    pub pending: u64,
+    pub size_bytes: u64,
+    pub max_size_bytes: u64,
+    pub written: u64,
+    pub sent: u64,
+    pub dropped: u64,
+    pub rejected: u64,
+}
+
+/// Очередь отправки в pr на диске (append-only), нужна для того, чтобы не терять данные, пока pr недоступен.
+/// Все данные для pr сначала пишутся в очередь (см. spool), grpc клиент вычитывает их по порядку, а после того, как pr
+/// подтвердил сохранение, подтверждает их в очереди (commit). После переподключения клиент продолжает с последней
+/// подтвержденной позиции, она же сохраняется на диск, поэтому после перезапуска ipm отправка продолжается с того же
+/// места. Часть сообщений при этом может быть отправлена повторно, дубликаты pr отбрасывает по номеру (sequence).
+/// Очередь разбита на сегменты (файлы), полностью отправленные сегменты удаляются. Если размер очереди достиг
+/// max_size_mb, то новые сообщения отбрасываются (счетчик dropped), уже сохраненные данные не теряются.
+/// Работа с файлами блокирующая, поэтому очередью владеет отдельный поток, все операции передаются ему через
+/// ограниченный канал (COMMANDS_CAPACITY) и выполняются в порядке поступления.
+#[derive(Clone)]
+pub struct Outbox {
+    id: String,
+    commands: mpsc::Sender<Command>,
+    notify: Arc<Notify>,
+}
+
+enum Command {
+    Push(Item),
+    Read(oneshot::Sender<io::Result<Option<Record>>>),
+    Commit(u64),
+    Reject(u64),
+    Rewind,
+    Flush(oneshot::Sender<()>),
+    Stats(oneshot::Sender<OutboxStats>),
+}
+
+impl Outbox {
+    pub fn open(cfg: &OutboxCfg) -> anyhow::Result<Self> {
+        let id = read_or_create_id(Path::new(&cfg.dir))
+            .with_context(|| format!("could not read outbox id in {}", cfg.dir))?;
+        let queue = Queue::open(cfg).with_context(|| format!("could not open outbox in {}", cfg.dir))?;
+        info!(
+            "outbox opened: {}, id: {}, pending: {}, size: {} bytes",
+            cfg.dir, id, queue.pending, queue.size
+        );
+
+        let (commands, receiver) = mpsc::channel(COMMANDS_CAPACITY);
+        let notify = Arc::new(Notify::new());
+        let n = notify.clone();
+        thread::spawn(move || serve(queue, receiver, n));
+
+        Ok(Outbox { id, commands, notify })
+    }
+
+    /// Идентификатор очереди, создается вместе с очередью. Если каталог очереди удален, то нумерация сообщений
+    /// начинается заново, но уже с другим идентификатором, поэтому новые сообщения не будут приняты pr за дубликаты.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub async fn push(&self, item: Item) {
+        self.send(Command::Push(item)).await;
+    }
+
+    /// Следующее сообщение по порядку, если очередь пуста - ждем, None - сигнал на завершение
+    pub async fn next(&self, shutdown: &CancellationToken) -> anyhow::Result<Option<Record>> {
+        loop {
+            if let Some(record) = self.request(Command::Read).await? {
+                return Ok(Some(record));
+            }
+
+            tokio::select! {
+                _ = self.notify.notified() => {},
+                _ = shutdown.cancelled() => return Ok(None),
+            }
+        }
+    }
+
+    /// Подтверждение сохранения всех прочитанных сообщений с номером до sequence включительно
+    pub async fn commit(&self, sequence: u64) {
+        self.send(Command::Commit(sequence)).await;
+    }
+
+    /// Сообщение sequence отклонено pr (повторно отправлять его бессмысленно): подтверждается так же, как
+    /// сохраненное, но учитывается в счетчике rejected
+    pub async fn reject(&self, sequence: u64) {
+        self.send(Command::Reject(sequence)).await;
+    }
+
+    /// Возврат к последней подтвержденной позиции (при переподключении)
+    pub async fn rewind(&self) {
+        self.send(Command::Rewind).await;
+    }
+
+    /// Сброс буфера записи и сохранение подтвержденной позиции на диск, завершается после записи
+    pub async fn flush(&self) {
+        self.request(Command::Flush).await
+    }
+
+    pub async fn stats(&self) -> OutboxStats {
+        self.request(Command::Stats).await
+    }
+
+    async fn send(&self, command: Command) {
+        if self.commands.send(command).await.is_err() {
+            panic!("outbox thread is stopped");
+        }
+    }
+
+    async fn request<T>(&self, command: fn(oneshot::Sender<T>) -> Command) -> T {
+        let (reply, result) = oneshot::channel();
+        self.send(command(reply)).await;
+        result.await.expect("outbox thread is stopped")
+    }
+}
+
+/// Поток очереди: выполняет операции по порядку, пока есть хотя бы один экземпляр Outbox
+fn serve(mut queue: Queue, mut commands: mpsc::Receiver<Command>, notify: Arc<Notify>) {
+    while let Some(command) = commands.blocking_recv() {
+        match command {
+            Command::Push(item) => {
+                if let Err(err) = queue.push(&item) {
+                    error!("outbox write failed: {}", err);
+                }
+                notify.notify_one();
+            }
+            Command::Read(reply) => {
+                let _ = reply.send(queue.read());
+            }
+            Command::Commit(sequence) => queue.commit(sequence),
+            Command::Reject(sequence) => {
+                queue.commit(sequence);
+                queue.rejected += 1;
+            }
+            Command::Rewind => queue.rewind(),
+            Command::Flush(reply) => {
+                if let Err(err) = queue.flush() {
+                    error!("outbox flush failed: {}", err);
+                }
+                let _ = reply.send(());
+            }
+            Command::Stats(reply) => {
+                let _ = reply.send(queue.stats());
+            }
+        }
+    }
+}
+
+/// Пишет в очередь все данные для pr, работает все время, пока запущен ipm, независимо от подключения к pr
+pub fn spool(
+    outbox: Outbox,
+    mut trade_receiver: Receiver<DomainTrade>,
+    mut order_book_receiver: Receiver<DomainOrderBook>,
+    mut candle_receiver: Receiver<DomainCandle>,
+    shutdown: CancellationToken,
+) {
+    tokio::spawn(async move {
+        let mut flush = time::interval(FLUSH_INTERVAL);
+        loop {
+            let item = tokio::select! {
+                val = trade_receiver.recv() => val.map(|t| Item::Trade(Trade::from(t))),
+                val = order_book_receiver.recv() => val.map(|ob| Item::OrderBook(OrderBook::from(ob))),
+                val = candle_receiver.recv() => val.map(|c| Item::Candle(Candle::from(c))),
+                _ = flush.tick() => {
+                    outbox.flush().await;
+                    continue;
+                }
+                _ = shutdown.cancelled() => {
+                    outbox.flush().await;
+                    info!("outbox spooling finished");
+                    return;
+                }
+            };
+
+            match item {
+                Ok(item) => outbox.push(item).await,
+                Err(err) => warn!("outbox spooling: {}", err),
+            }
+        }
+    });
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Position {
+    segment: u64,
+    offset: u64,
+}
+
+/// Прочитанное, но еще не подтвержденное сообщение: номер, позиция после него и размер
+#[derive(Debug)]
+struct InFlight {
+    sequence: u64,
+    end: Position,
+    len: u64,
+}
+
+struct Queue {
+    dir: PathBuf,
+    segment_size: u64,
+    max_size: u64,
+    /// Все сегменты по порядку (номер и размер), последний - тот, в который идет запись
+    segments: VecDeque<(u64, u64)>,
+    writer: BufWriter<File>,
+    reader: Option<(u64, BufReader<File>)>,
+    read: Position,
+    committed: Position,
+    in_flight: VecDeque<InFlight>,
+    next_sequence: u64,
+    /// Не отправлено (количество сообщений и байт)
+    pending: u64,
+    size: u64,
+    written: u64,
+    sent: u64,
+    dropped: u64,
+    rejected: u64,
+}
+
+impl Queue {
+    fn open(cfg: &OutboxCfg) -> io::Result<Self> {
+        let dir = PathBuf::from(&cfg.dir);
+        fs::create_dir_all(&dir)?;
+
+        let mut ids: Vec<u64> = fs::read_dir(&dir)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| segment_id(&entry.path()))
+            .collect();
+        ids.sort_unstable();
+
+        let (mut committed, mut next_sequence) = read_position(&dir)?.unwrap_or((
+            Position {
+                segment: ids.first().copied().unwrap_or(0),
+                offset: 0,
+            },
+            0,
+        ));
+
+        // Сегменты до подтвержденной позиции уже отправлены, в остальных считаем сообщения и проверяем целостность:
+        // если ipm упал во время записи, то последнее сообщение может быть записано не полностью
+        let mut segments = VecDeque::new();
+        let mut pending = 0;
+        for id in ids {
+            let path = segment_path(&dir, id);
+            if id < committed.segment {
+                fs::remove_file(path)?;
+                continue;
+            }
+
+            let file = OpenOptions::new().write(true).open(&path)?;
+            let len = file.metadata()?.len();
+            if id == committed.segment {
+                committed.offset = committed.offset.min(len);
+            }
+
+            let from = if id == committed.segment { committed.offset } else { 0 };
+            let (count, valid_len, last_sequence) = scan(&path, from)?;
+            if len > valid_len {
+                warn!("outbox segment {} is truncated to {} bytes", id, valid_len);
+                file.set_len(valid_len)?;
+            }
+
+            pending += count;
+            segments.push_back((id, valid_len));
+            if let Some(sequence) = last_sequence {
+                next_sequence = next_sequence.max(sequence + 1);
+            }
+        }
+
+        if segments.is_empty() {
+            segments.push_back((committed.segment + 1, 0));
+        }
+
+        // Подтвержденного сегмента нет на диске - продолжаем с начала первого из оставшихся
+        if !segments.iter().any(|(id, _)| *id == committed.segment) {
+            committed = Position {
+                segment: segments[0].0,
+                offset: 0,
+            };
+        }
+
+        let (last, _) = *segments.back().unwrap();
+        let writer = open_writer(&dir, last)?;
+        let size = segments.iter().map(|(_, size)| size).sum::<u64>() - committed.offset;
+
+        Ok(Queue {
+            dir,
+            segment_size: cfg.segment_size_mb * MB,
+            max_size: cfg.max_size_mb * MB,
+            segments,
+            writer,
+            reader: None,
+            read: committed,
+            committed,
+            in_flight: VecDeque::new(),
+            next_sequence,
+            pending,
+            size,
+            written: 0,
+            sent: 0,
+            dropped: 0,
+            rejected: 0,
+        })
+    }
+
+    fn push(&mut self, item: &Item) -> io::Result<()> {
+        let (kind, data) = item.encode();
+        let len = HEADER_LEN + data.len() as u64;
+        if self.size + len > self.max_size {
+            self.dropped += 1;
+            if self.dropped % 1000 == 1 {
+                warn!("outbox is full ({} bytes), dropped: {}", self.size, self.dropped);
+            }
+            return Ok(());
+        }
+
+        let (id, segment_size) = *self.segments.back().unwrap();
+        if segment_size > 0 && segment_size + len > self.segment_size {
+            self.writer.flush()?;
+            self.writer = open_writer(&self.dir, id + 1)?;
+            self.segments.push_back((id + 1, 0));
+        }
+
+        self.writer.write_all(&[kind])?;
+        self.writer.write_all(&self.next_sequence.to_le_bytes())?;
+        self.writer.write_all(&(data.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&data)?;
+
+        self.segments.back_mut().unwrap().1 += len;
+        self.next_sequence += 1;
+        self.size += len;
+        self.pending += 1;
+        self.written += 1;
+        Ok(())
+    }
+
+    fn read(&mut self) -> io::Result<Option<Record>> {
+        loop {
+            let (last, _) = *self.segments.back().unwrap();
+            let segment_size = self.segment_len(self.read.segment);
+            if self.read.offset >= segment_size {
+                if self.read.segment >= last {
+                    return Ok(None);
+                }
+
+                // Сегмент прочитан полностью, переходим к следующему
+                let next = self.segments.iter().find(|(id, _)| *id > self.read.segment).unwrap().0;
+                self.read = Position {
+                    segment: next,
+                    offset: 0,
+                };
+                continue;
+            }
+
+            if self.read.segment == last {
+                self.writer.flush()?;
+            }
+
+            let (kind, sequence, data) = match read_record(self.reader(self.read)?) {
+                Ok(record) => record,
+                Err(err) => {
+                    // Позиция ридера неизвестна, при следующем чтении он будет открыт заново
+                    self.reader = None;
+                    return Err(err);
+                }
+            };
+
+            let len = HEADER_LEN + data.len() as u64;
+            self.read.offset += len;
+            self.in_flight.push_back(InFlight {
+                sequence,
+                end: self.read,
+                len,
+            });
+
+            // Пропущенное сообщение подтвердится вместе со следующим
+            match Item::decode(kind, &data) {
+                Ok(item) => return Ok(Some(Record { sequence, item })),
+                Err(err) => error!("outbox item {} is skipped: {}", sequence, err),
+            }
+        }
+    }
+
+    fn commit(&mut self, sequence: u64) {
+        while self.in_flight.front().is_some_and(|f| f.sequence <= sequence) {
+            let f = self.in_flight.pop_front().unwrap();
+            self.committed = f.end;
+            self.pending -= 1;
+            self.size -= f.len;
+            self.sent += 1;
+        }
+
+        // Полностью отправленные сегменты больше не нужны (сегмент, в который идет запись, не удаляется)
+        while self.segments.len() > 1 && self.segments[0].0 < self.committed.segment {
+            let (id, _) = self.segments.pop_front().unwrap();
+            if let Err(err) = fs::remove_file(segment_path(&self.dir, id)) {
+                error!("could not remove outbox segment {}: {}", id, err);
+            }
+        }
+    }
+
+    fn rewind(&mut self) {
+        self.read = self.committed;
+        self.in_flight.clear();
+        self.reader = None;
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        let tmp = self.dir.join(format!("{}.tmp", POSITION_FILE));
+        let position = format!(
+            "{} {} {}",
+            self.committed.segment, self.committed.offset, self.next_sequence
+        );
+        fs::write(&tmp, position)?;
+        fs::rename(tmp, self.dir.join(POSITION_FILE))
+    }
+
+    fn stats(&self) -> OutboxStats {
+        OutboxStats {
+            pending: self.pending,
+            size_bytes: self.size,
+            max_size_bytes: self.max_size,
+            written: self.written,
+            sent: self.sent,
+            dropped: self.dropped,
+            rejected: self.rejected,
+        }
+    }
+
+    fn segment_len(&self, id: u64) -> u64 {
+        self.segments
+            .iter()
+            .find(|(i, _)| *i == id)
+            .map(|(_, size)| *size)
+            .unwrap_or(0)
+    }
+
+    /// Ридер, установленный на позицию (открывается заново только при смене сегмента или после rewind)
+    fn reader(&mut self, position: Position) -> io::Result<&mut BufReader<File>> {
+        match &self.reader {
+            Some((id, _)) if *id == position.segment => {}
+            _ => {
+                let mut file = File::open(segment_path(&self.dir, position.segment))?;
+                file.seek(SeekFrom::Start(position.offset))?;
+                self.reader = Some((position.segment, BufReader::new(file)));
+            }
+        }
+
+        Ok(&mut self.reader.as_mut().unwrap().1)
+    }
+}
+
+fn segment_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{}{:020}.{}", SEGMENT_PREFIX, id, SEGMENT_EXT))
+}
+
+fn segment_id(path: &Path) -> Option<u64> {
+    let name = path.file_name()?.to_str()?;
+    name.strip_prefix(SEGMENT_PREFIX)?
+        .strip_suffix(&format!(".{}", SEGMENT_EXT))?
+        .parse()
+        .ok()
+}
+
+fn open_writer(dir: &Path, id: u64) -> io::Result<BufWriter<File>> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(segment_path(dir, id))?;
+    Ok(BufWriter::new(file))
+}
+
+/// Подтвержденная позиция и номер следующего сообщения (номер нужен на случай, если все сегменты удалены)
+fn read_position(dir: &Path) -> io::Result<Option<(Position, u64)>> {
+    let data = match fs::read_to_string(dir.join(POSITION_FILE)) {
+        Ok(data) => data,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let mut parts = data.split_whitespace().map(|p| p.parse::<u64>());
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(segment)), Some(Ok(offset)), Some(Ok(sequence))) => Ok(Some((Position { segment, offset }, sequence))),
+        _ => Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("invalid outbox position: {}", data),
+        )),
+    }
+}
+
+fn read_or_create_id(dir: &Path) -> io::Result<String> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(ID_FILE);
+    match fs::read_to_string(&path) {
+        Ok(id) => Ok(id.trim().to_string()),
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            let id = format!("{:016x}", rand::random::<u64>());
+            fs::write(path, &id)?;
+            Ok(id)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Количество целых сообщений в сегменте начиная с from, длина сегмента до конца последнего целого сообщения
+/// и номер последнего сообщения
+fn scan(path: &Path, from: u64) -> io::Result<(u64, u64, Option<u64>)> {
+    let mut reader = BufReader::new(File::open(path)?);
+    reader.seek(SeekFrom::Start(from))?;
+
+    let (mut count, mut valid_len, mut last_sequence) = (0, from, None);
+    while let Ok((_, sequence, data)) = read_record(&mut reader) {
+        count += 1;
+        valid_len += HEADER_LEN + data.len() as u64;
+        last_sequence = Some(sequence);
+    }
+
+    Ok((count, valid_len, last_sequence))
+}
+
+/// Запись в сегменте: тип (1 байт), номер (8 байт), длина (4 байта), сообщение в protobuf, числа - little endian
+fn read_record<R: Read>(reader: &mut R) -> io::Result<(u8, u64, Vec<u8>)> {
+    let mut header = [0u8; HEADER_LEN as usize];
+    reader.read_exact(&mut header)?;
+    let mut sequence = [0u8; 8];
+    sequence.copy_from_slice(&header[1..9]);
+    let mut len = [0u8; 4];
+    len.copy_from_slice(&header[9..]);
+    let mut data = vec![0u8; u32::from_le_bytes(len) as usize];
+    reader.read_exact(&mut data)?;
+    Ok((header[0], u64::from_le_bytes(sequence), data))
+}
@@ -0,0 +1,182 @@
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use log::{error, warn};
+use rand::Rng;
+use tokio::time;
+use tokio_util::sync::CancellationToken;
+
+use crate::settings::Reconnect;
+
+#[derive(Clone, Debug)]
+pub enum ConnectionState {
+    Idle,
+    Connecting {
+        attempt: u32,
+    },
+    Connected,
+    Waiting {
+        attempt: u32,
+        next_attempt: DateTime<Utc>,
+        error: String,
+    },
+    CircuitOpen {
+        attempt: u32,
+        next_attempt: DateTime<Utc>,
+        error: String,
+    },
+    /// Переподключение не имеет смысла (например, отозван токен), нужно вмешательство и перезапуск сервиса
+    Failed {
+        error: String,
+    },
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ConnectionState::Idle => "idle",
+            ConnectionState::Connecting { .. } => "connecting",
+            ConnectionState::Connected => "connected",
+            ConnectionState::Waiting { .. } => "waiting",
+            ConnectionState::CircuitOpen { .. } => "circuit_open",
+            ConnectionState::Failed { .. } => "failed",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Текущее состояние подключения клиента (ws или grpc), его можно посмотреть через grpc сервис Control
+#[derive(Clone)]
+pub struct ConnectionMonitor {
+    name: String,
+    state: Arc<RwLock<(ConnectionState, DateTime<Utc>)>>,
+}
+
+impl ConnectionMonitor {
+    pub fn new(name: &str) -> Self {
+        ConnectionMonitor {
+            name: name.to_string(),
+            state: Arc::new(RwLock::new((ConnectionState::Idle, Utc::now()))),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Состояние и время его изменения
+    pub fn state(&self) -> (ConnectionState, DateTime<Utc>) {
+        self.state.read().unwrap().clone()
+    }
+
+    pub fn set(&self, state: ConnectionState) {
+        *self.state.write().unwrap() = (state, Utc::now());
+    }
+
+    pub fn connected(&self) {
+        self.set(ConnectionState::Connected);
+    }
+}
+
+/// Переподключение с экспоненциально растущей задержкой и случайным разбросом (jitter).
+/// После max_attempts неудачных попыток подряд попытки приостанавливаются на circuit_open_secs (circuit open),
+/// затем делается одна попытка, если и она неудачная - снова пауза. Успешное подключение сбрасывает счетчик попыток.
+pub struct Reconnector {
+    cfg: Reconnect,
+    monitor: ConnectionMonitor,
+    failures: u32,
+}
+
+impl Reconnector {
+    pub fn new(cfg: Reconnect, monitor: ConnectionMonitor) -> Self {
+        Reconnector {
+            cfg,
+            monitor,
+            failures: 0,
+        }
+    }
+
+    pub fn monitor(&self) -> ConnectionMonitor {
+        self.monitor.clone()
+    }
+
+    pub fn connecting(&self) {
+        self.monitor.set(ConnectionState::Connecting {
+            attempt: self.failures + 1,
+        });
+    }
+
+    /// Фатальная ошибка, больше не переподключаемся
+    pub fn fail<E: fmt::Display>(&self, err: &E) {
+        error!("{} client failed: {}, reconnection stopped", self.monitor.name(), err);
+        self.monitor.set(ConnectionState::Failed { error: err.to_string() });
+    }
+
+    /// Ждем перед следующей попыткой подключения, false - если за время ожидания пришел сигнал на завершение
+    pub async fn wait<E: fmt::Display>(&mut self, err: &E, shutdown: &CancellationToken) -> bool {
+        // Если подключение было установлено, то считаем, что это новая серия попыток
+        if let (ConnectionState::Connected, _) = self.monitor.state() {
+            self.failures = 0;
+        }
+        self.failures += 1;
+
+        let error = err.to_string();
+        let circuit_open = self.cfg.max_attempts > 0 && self.failures >= self.cfg.max_attempts;
+        let delay = match circuit_open {
+            true => Duration::from_secs(self.cfg.circuit_open_secs),
+            false => self.delay(),
+        };
+
+        let next_attempt = Utc::now() + chrono::Duration::from_std(delay).unwrap_or_else(|_| chrono::Duration::zero());
+        let attempt = self.failures;
+
+        if circuit_open {
+            error!(
+                "{} client failed {} times in a row: {}, circuit is open until {}",
+                self.monitor.name(),
+                attempt,
+                error,
+                next_attempt
+            );
+            self.monitor.set(ConnectionState::CircuitOpen {
+                attempt,
+                next_attempt,
+                error,
+            });
+        } else {
+            warn!(
+                "{} client not running (attempt {}): {}, retry will be in {} ms",
+                self.monitor.name(),
+                attempt,
+                error,
+                delay.as_millis()
+            );
+            self.monitor.set(ConnectionState::Waiting {
+                attempt,
+                next_attempt,
+                error,
+            });
+        }
+
+        tokio::select! {
+            _ = time::sleep(delay) => true,
+            _ = shutdown.cancelled() => false,
+        }
+    }
+
+    fn delay(&self) -> Duration {
+        let exp = self.cfg.multiplier.powi(self.failures as i32 - 1);
+        let base = (self.cfg.initial_delay_ms as f64 * exp).min(self.cfg.max_delay_ms as f64);
+
+        // jitter в пределах 0..=1 гарантирует проверка настроек (Reconnect::validate)
+        let jitter = self.cfg.jitter;
+        let factor = match jitter > 0.0 {
+            true => rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter),
+            false => 1.0,
+        };
+
+        Duration::from_millis((base * factor).min(self.cfg.max_delay_ms as f64) as u64)
+    }
+}
@@ -1,21 +1,27 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-const CANDLE_INTERVAL: &str = "1min";
-
 #[derive(Serialize, Debug)]
-pub struct SubscribeCandleReq {
+pub struct CandleReq {
     event: String,
     figi: String,
     interval: String,
 }
 
-impl SubscribeCandleReq {
-    pub fn prepare(figi: String) -> SubscribeCandleReq {
-        SubscribeCandleReq {
-            event: "candle:subscribe".to_string(),
+impl CandleReq {
+    pub fn subscribe(figi: String, interval: String) -> CandleReq {
+        CandleReq::prepare("candle:subscribe", figi, interval)
+    }
+
+    pub fn unsubscribe(figi: String, interval: String) -> CandleReq {
+        CandleReq::prepare("candle:unsubscribe", figi, interval)
+    }
+
+    fn prepare(event: &str, figi: String, interval: String) -> CandleReq {
+        CandleReq {
+            event: event.to_string(),
             figi,
-            interval: CANDLE_INTERVAL.to_string(),
+            interval,
         }
     }
 }
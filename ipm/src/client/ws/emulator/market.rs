@@ -0,0 +1,214 @@
+use chrono::{DateTime, Datelike, Duration, DurationRound, TimeZone, Utc};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::domain::{candle::Candle, order_book::OrderBook};
+use crate::settings::{Emulator, Instrument};
+
+use super::process::{self, Process};
+use super::scenario::{Action, Side};
+
+/// Рынок по одному инструменту: цена меняется по заданному процессу, каждая сделка обновляет свечи по всем интервалам,
+/// книга заказов строится вокруг последней цены (лучший bid всегда ниже лучшего ask на spread_ticks шагов цены).
+/// Для сделок и для книги заказов используются разные генераторы, поэтому последовательность сделок зависит только
+/// от seed и не зависит от того, как по времени чередуются сделки и обновления книги.
+pub struct Market {
+    figi: String,
+    depth: u32,
+    tick: f64,
+    spread_ticks: u32,
+    max_lot: u64,
+    process: Box<dyn Process>,
+    trade_rng: StdRng,
+    book_rng: StdRng,
+    price: f64,
+    candles: Vec<(String, Option<Candle>)>,
+    // Состояние, которое меняется сценарием (см. apply)
+    empty_side: Option<Side>,
+    frozen: bool,
+    rate: Option<u64>,
+    disorder: (u32, u64),
+}
+
+impl Market {
+    pub fn new(cfg: &Emulator, instrument: &Instrument, seed: u64) -> Self {
+        // У каждого инструмента свой seed, чтобы последовательности не зависели от набора и порядка инструментов
+        let seed = seed ^ fnv1a(&instrument.figi);
+        let mut process = process::create(&cfg.process(&instrument.figi));
+        let mut trade_rng = StdRng::seed_from_u64(seed);
+        let price = round(process.next(&mut trade_rng), cfg.tick).max(cfg.tick);
+
+        Market {
+            figi: instrument.figi.clone(),
+            depth: instrument.order_book_depth,
+            tick: cfg.tick,
+            spread_ticks: cfg.spread_ticks,
+            max_lot: cfg.max_lot,
+            process,
+            trade_rng,
+            book_rng: StdRng::seed_from_u64(seed.wrapping_add(1)),
+            price,
+            candles: instrument.candle_intervals.iter().map(|i| (i.clone(), None)).collect(),
+            empty_side: None,
+            frozen: false,
+            rate: None,
+            disorder: (0, 0),
+        }
+    }
+
+    /// Применяет действие сценария
+    pub fn apply(&mut self, action: &Action) {
+        match *action {
+            Action::Shift(factor) => {
+                self.process.shift(factor);
+                self.price = round(self.price * factor, self.tick).max(self.tick);
+            }
+            Action::EmptyBook(side) => self.empty_side = side,
+            Action::Freeze(frozen) => self.frozen = frozen,
+            Action::Rate(rate) => self.rate = rate,
+            Action::Disorder { count, max_shift_ms } => self.disorder = (count, max_shift_ms),
+        }
+    }
+
+    /// Замерзший инструмент не торгуется и книга заказов по нему не обновляется
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    pub fn figi(&self) -> &str {
+        &self.figi
+    }
+
+    pub fn price(&self) -> f64 {
+        self.price
+    }
+
+    /// Очередная сделка, возвращает свечи по всем интервалам с учетом этой сделки
+    pub fn trade(&mut self, now: DateTime<Utc>) -> Vec<Candle> {
+        self.price = round(self.process.next(&mut self.trade_rng), self.tick).max(self.tick);
+        let volume = self.trade_rng.gen_range(1..=self.max_lot);
+        let sent = sent(&mut self.disorder, &mut self.trade_rng, now);
+        let (figi, price) = (&self.figi, self.price as f32);
+
+        self.candles
+            .iter_mut()
+            .map(|(interval, current)| {
+                let time = candle_start(now, interval);
+                let candle = match current.take() {
+                    Some(mut candle) if candle.time == time => {
+                        candle.high = candle.high.max(price);
+                        candle.low = candle.low.min(price);
+                        candle.close = price;
+                        candle.volume += volume;
+                        candle.sent = sent;
+                        candle.received = now;
+                        candle
+                    }
+                    _ => Candle {
+                        figi: figi.clone(),
+                        interval: interval.clone(),
+                        open: price,
+                        close: price,
+                        high: price,
+                        low: price,
+                        volume,
+                        time,
+                        sent,
+                        received: now,
+                    },
+                };
+                *current = Some(candle.clone());
+                candle
+            })
+            .collect()
+    }
+
+    pub fn order_book(&mut self, now: DateTime<Utc>) -> OrderBook {
+        // Лучший bid на половину спреда ниже цены (с округлением вниз), но не ниже шага цены,
+        // лучший ask - на spread_ticks выше bid
+        let best_bid = (self.price - (self.spread_ticks / 2) as f64 * self.tick).max(self.tick);
+        let best_ask = best_bid + self.spread_ticks as f64 * self.tick;
+        let (tick, max_lot) = (self.tick, self.max_lot);
+        let rng = &mut self.book_rng;
+
+        let mut level =
+            |best: f64, i: u32, sign: f64| (round(best + sign * i as f64 * tick, tick), rng.gen_range(1..=max_lot));
+
+        // При цене, близкой к нулю, уровней bid может быть меньше глубины
+        let bids: Vec<_> = (0..self.depth)
+            .map(|i| level(best_bid, i, -1.0))
+            .take_while(|(price, _)| *price > tick / 2.0)
+            .map(|(price, lot)| (price as f32, lot))
+            .collect();
+        let asks: Vec<_> = (0..self.depth)
+            .map(|i| level(best_ask, i, 1.0))
+            .map(|(price, lot)| (price as f32, lot))
+            .collect();
+
+        let (bids, asks) = match self.empty_side {
+            Some(Side::Bids) => (vec![], asks),
+            Some(Side::Asks) => (bids, vec![]),
+            Some(Side::Both) => (vec![], vec![]),
+            None => (bids, asks),
+        };
+
+        let sent = sent(&mut self.disorder, &mut self.book_rng, now);
+        OrderBook::new(self.figi.clone(), self.depth, bids, asks, sent, now)
+    }
+
+    /// Случайная пауза от 0.5 до 1.5 среднего, для сделок и книги заказов свои генераторы.
+    /// Во время всплеска активности (сценарий burst) пауза фиксированная - исходя из заданной частоты.
+    pub fn trade_delay(&mut self, avg_ms: u64) -> std::time::Duration {
+        delay(&mut self.trade_rng, avg_ms, self.rate)
+    }
+
+    pub fn order_book_delay(&mut self, avg_ms: u64) -> std::time::Duration {
+        delay(&mut self.book_rng, avg_ms, self.rate)
+    }
+}
+
+/// Время отправки, при беспорядке (сценарий out_of_order) сдвинуто в прошлое на случайную величину,
+/// сдвиг берется из генератора того потока (сделки или книга заказов), для которого формируется сообщение
+fn sent(disorder: &mut (u32, u64), rng: &mut StdRng, now: DateTime<Utc>) -> DateTime<Utc> {
+    match *disorder {
+        (0, _) => now,
+        (count, max_shift_ms) => {
+            *disorder = (count - 1, max_shift_ms);
+            now - Duration::milliseconds(rng.gen_range(0..=max_shift_ms) as i64)
+        }
+    }
+}
+
+fn delay(rng: &mut StdRng, avg_ms: u64, rate: Option<u64>) -> std::time::Duration {
+    match rate {
+        Some(rate) => std::time::Duration::from_micros(1_000_000 / rate),
+        None => std::time::Duration::from_millis(rng.gen_range(avg_ms / 2..=avg_ms + avg_ms / 2)),
+    }
+}
+
+/// Цена кратна шагу
+fn round(price: f64, tick: f64) -> f64 {
+    (price / tick).round() * tick
+}
+
+/// Начало свечи, в которую попадает время, интервалы: https://tinkoffcreditsystems.github.io/invest-openapi/marketdata/
+fn candle_start(now: DateTime<Utc>, interval: &str) -> DateTime<Utc> {
+    let day = Utc.ymd(now.year(), now.month(), now.day()).and_hms(0, 0, 0);
+    let duration = match interval {
+        "week" => return day - Duration::days(now.weekday().num_days_from_monday() as i64),
+        "month" => return Utc.ymd(now.year(), now.month(), 1).and_hms(0, 0, 0),
+        "day" => return day,
+        "hour" => Duration::hours(1),
+        "2hour" => Duration::hours(2),
+        "4hour" => Duration::hours(4),
+        min => Duration::minutes(min.trim_end_matches("min").parse().unwrap_or(1)),
+    };
+    now.duration_trunc(duration).unwrap_or(now)
+}
+
+/// Стабильный (в отличие от DefaultHasher) хеш, чтобы seed инструмента не менялся между версиями Rust
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
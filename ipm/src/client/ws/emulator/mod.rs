@@ -0,0 +1,126 @@
+use chrono::Utc;
+use log::{error, info, warn};
+use tokio::time::{self, Instant};
+
+use crate::domain::{candle::Candle, order_book::OrderBook, trade::Trade};
+use crate::settings::{Emulator, Instrument};
+use shared::channel::BroadcastSender;
+
+pub use market::Market;
+use scenario::Action;
+pub use scenario::Scenario;
+
+mod market;
+pub mod process;
+pub mod scenario;
+
+/// Эмулятор рынка вместо подключения к Тинькофф: по каждому инструменту свой рынок (см. Market),
+/// сделки транслируются в виде trade и candle (как и при работе с ws), книга заказов обновляется по своему таймеру.
+/// Если задан сценарий, то в указанное время с рынком происходят заданные события (гэпы, обвалы, всплески и т.д.).
+pub async fn run(
+    cfg: &Emulator,
+    instruments: &[Instrument],
+    scenario: Option<&Scenario>,
+    trade_sender: BroadcastSender<Trade>,
+    order_book_sender: BroadcastSender<OrderBook>,
+    candle_sender: BroadcastSender<Candle>,
+) -> anyhow::Result<()> {
+    let seed = cfg.seed.unwrap_or_else(rand::random);
+    info!("market emulator started, seed: {}", seed);
+
+    if let Some(scenario) = scenario {
+        for figi in scenario.instruments.keys() {
+            if !instruments.iter().any(|i| &i.figi == figi) {
+                warn!(
+                    "instrument {} from the scenario is not emulated, its events are ignored",
+                    figi
+                );
+            }
+        }
+    }
+
+    for instrument in instruments.iter() {
+        let market = Market::new(cfg, instrument, seed);
+        let timeline = scenario.map(|s| s.timeline(&instrument.figi)).unwrap_or_default();
+        tokio::spawn(emulate(
+            market,
+            cfg.clone(),
+            timeline,
+            trade_sender.clone(),
+            order_book_sender.clone(),
+            candle_sender.clone(),
+        ));
+    }
+
+    Ok(())
+}
+
+async fn emulate(
+    mut market: Market,
+    cfg: Emulator,
+    timeline: Vec<(std::time::Duration, Action)>,
+    trade_sender: BroadcastSender<Trade>,
+    order_book_sender: BroadcastSender<OrderBook>,
+    candle_sender: BroadcastSender<Candle>,
+) {
+    let start = Instant::now();
+    let mut next_trade = start;
+    let mut next_order_book = start;
+    let mut actions = timeline.into_iter().peekable();
+
+    loop {
+        // Если сценарий закончился, то ветка с действиями отключается
+        let next_action = actions.peek().map(|(at, _)| start + *at).unwrap_or(start);
+
+        tokio::select! {
+            _ = time::sleep_until(next_action), if actions.peek().is_some() => {
+                if let Some((_, action)) = actions.next() {
+                    info!("{}: scenario action {:?}", market.figi(), action);
+                    market.apply(&action);
+                }
+                // После заморозки сделки не должны "догонять" пропущенное время
+                let now = Instant::now();
+                next_trade = next_trade.max(now);
+                next_order_book = next_order_book.max(now);
+            },
+            _ = time::sleep_until(next_trade), if !market.is_frozen() => {
+                // Рынок живет независимо от того, есть ли получатели, иначе сломается воспроизводимость по seed
+                for candle in market.trade(Utc::now()) {
+                    if trade_sender.receiver_count() > 0 {
+                        let trade = Trade::new(
+                            candle.close,
+                            candle.volume,
+                            candle.figi.clone(),
+                            candle.interval.clone(),
+                            candle.time,
+                            candle.sent,
+                            candle.received,
+                        );
+
+                        if let Err(err) = trade_sender.send(trade) {
+                            error!("send trade failed, error: {}", err);
+                        }
+                    }
+
+                    if candle_sender.receiver_count() > 0 {
+                        if let Err(err) = candle_sender.send(candle) {
+                            error!("send candle failed, error: {}", err);
+                        }
+                    }
+                }
+                next_trade += market.trade_delay(cfg.trade_interval_ms);
+            },
+            _ = time::sleep_until(next_order_book), if !market.is_frozen() => {
+                let order_book = market.order_book(Utc::now());
+                if order_book_sender.receiver_count() > 0 {
+                    if let Err(err) = order_book_sender.send(order_book) {
+                        error!("send order book failed, error: {}", err);
+                    }
+                }
+                next_order_book += market.order_book_delay(cfg.order_book_interval_ms);
+            },
+            // Инструмент заморожен, и сценарий закончился - больше ничего не произойдет
+            else => return,
+        }
+    }
+}
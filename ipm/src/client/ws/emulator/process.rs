@@ -0,0 +1,94 @@
+use rand::rngs::StdRng;
+use rand_distr::{Distribution, StandardNormal};
+
+use crate::settings::PriceProcess;
+
+/// Случайный процесс изменения цены, один вызов next - один шаг (одна сделка).
+/// Новую модель достаточно реализовать здесь и добавить в PriceProcess (settings) и create.
+pub trait Process: Send {
+    fn next(&mut self, rng: &mut StdRng) -> f64;
+    /// Скачок цены (используется в сценариях), factor - множитель текущей цены
+    fn shift(&mut self, factor: f64);
+}
+
+pub fn create(cfg: &PriceProcess) -> Box<dyn Process> {
+    match *cfg {
+        PriceProcess::RandomWalk { price, step } => Box::new(RandomWalk { price, step }),
+        PriceProcess::Gbm {
+            price,
+            drift,
+            volatility,
+        } => Box::new(Gbm {
+            price,
+            drift,
+            volatility,
+        }),
+        PriceProcess::MeanReversion {
+            price,
+            mean,
+            speed,
+            volatility,
+        } => Box::new(MeanReversion {
+            price,
+            mean,
+            speed,
+            volatility,
+        }),
+    }
+}
+
+fn z(rng: &mut StdRng) -> f64 {
+    StandardNormal.sample(rng)
+}
+
+struct RandomWalk {
+    price: f64,
+    step: f64,
+}
+
+impl Process for RandomWalk {
+    fn next(&mut self, rng: &mut StdRng) -> f64 {
+        self.price += self.step * z(rng);
+        self.price
+    }
+
+    fn shift(&mut self, factor: f64) {
+        self.price *= factor;
+    }
+}
+
+struct Gbm {
+    price: f64,
+    drift: f64,
+    volatility: f64,
+}
+
+impl Process for Gbm {
+    fn next(&mut self, rng: &mut StdRng) -> f64 {
+        self.price *= (self.drift - self.volatility.powi(2) / 2.0 + self.volatility * z(rng)).exp();
+        self.price
+    }
+
+    fn shift(&mut self, factor: f64) {
+        self.price *= factor;
+    }
+}
+
+struct MeanReversion {
+    price: f64,
+    mean: f64,
+    speed: f64,
+    volatility: f64,
+}
+
+impl Process for MeanReversion {
+    fn next(&mut self, rng: &mut StdRng) -> f64 {
+        self.price += self.speed * (self.mean - self.price) + self.volatility * z(rng);
+        self.price
+    }
+
+    // Среднее не сдвигается, поэтому после скачка цена постепенно возвращается к нему
+    fn shift(&mut self, factor: f64) {
+        self.price *= factor;
+    }
+}
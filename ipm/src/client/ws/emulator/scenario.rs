@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use std::fs;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// Верхняя граница частоты всплеска: пауза между сообщениями считается в микросекундах и не должна стать нулевой
+const MAX_BURST_RATE: u64 = 100_000;
+
+/// Сценарий для эмулятора рынка: по каждому инструменту список событий с временем от начала эмуляции.
+/// Файл в формате YAML (JSON тоже подходит), пример: ipm/scenarios/edge_cases.yaml
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Scenario {
+    pub instruments: HashMap<String, Vec<Event>>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Event {
+    pub at_ms: u64,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum EventKind {
+    /// Мгновенный скачок цены на percent процентов (отрицательный - вниз)
+    Gap { percent: f64 },
+    /// Обвал на percent процентов и возврат к прежнему уровню через duration_ms
+    FlashCrash { percent: f64, duration_ms: u64 },
+    /// Пустая сторона книги заказов в течение duration_ms
+    EmptyBook { side: Side, duration_ms: u64 },
+    /// Инструмент "замерз": нет ни сделок, ни обновлений книги заказов
+    Freeze { duration_ms: u64 },
+    /// Всплеск активности: rate сделок и обновлений книги в секунду (не больше MAX_BURST_RATE)
+    Burst { rate: u64, duration_ms: u64 },
+    /// У следующих count сообщений время отправки (sent) сдвинуто в прошлое на случайную величину до max_shift_ms,
+    /// т.е. сообщения приходят не в порядке sent
+    OutOfOrder { count: u32, max_shift_ms: u64 },
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    Bids,
+    Asks,
+    Both,
+}
+
+/// Элементарное изменение состояния рынка (см. Market::apply), события сценария раскладываются на такие действия
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Shift(f64),
+    EmptyBook(Option<Side>),
+    Freeze(bool),
+    Rate(Option<u64>),
+    Disorder { count: u32, max_shift_ms: u64 },
+}
+
+impl Scenario {
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let scenario: Scenario = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        for (figi, events) in self.instruments.iter() {
+            for event in events.iter() {
+                let valid = match event.kind {
+                    EventKind::Gap { percent } | EventKind::FlashCrash { percent, .. } => percent > -100.0,
+                    EventKind::Burst { rate, .. } => rate > 0 && rate <= MAX_BURST_RATE,
+                    _ => true,
+                };
+
+                if !valid {
+                    anyhow::bail!("invalid scenario event for {}: {:?}", figi, event);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Действия по инструменту, упорядоченные по времени от начала эмуляции
+    pub fn timeline(&self, figi: &str) -> Vec<(Duration, Action)> {
+        let mut actions = Vec::new();
+        for event in self.instruments.get(figi).into_iter().flatten() {
+            let at = Duration::from_millis(event.at_ms);
+            let end = |duration_ms: u64| at + Duration::from_millis(duration_ms);
+
+            match event.kind {
+                EventKind::Gap { percent } => actions.push((at, Action::Shift(1.0 + percent / 100.0))),
+                EventKind::FlashCrash { percent, duration_ms } => {
+                    let factor = 1.0 + percent / 100.0;
+                    actions.push((at, Action::Shift(factor)));
+                    actions.push((end(duration_ms), Action::Shift(1.0 / factor)));
+                }
+                EventKind::EmptyBook { side, duration_ms } => {
+                    actions.push((at, Action::EmptyBook(Some(side))));
+                    actions.push((end(duration_ms), Action::EmptyBook(None)));
+                }
+                EventKind::Freeze { duration_ms } => {
+                    actions.push((at, Action::Freeze(true)));
+                    actions.push((end(duration_ms), Action::Freeze(false)));
+                }
+                EventKind::Burst { rate, duration_ms } => {
+                    actions.push((at, Action::Rate(Some(rate))));
+                    actions.push((end(duration_ms), Action::Rate(None)));
+                }
+                EventKind::OutOfOrder { count, max_shift_ms } => {
+                    actions.push((at, Action::Disorder { count, max_shift_ms }))
+                }
+            }
+        }
+
+        // Сортировка устойчивая, при одинаковом времени действия идут в порядке файла
+        actions.sort_by_key(|(at, _)| *at);
+        actions
+    }
+}
@@ -0,0 +1,76 @@
+use tungstenite::error::Error as TungsteniteError;
+use tungstenite::http::StatusCode;
+use tungstenite::protocol::CloseFrame;
+
+/// Ошибки ws клиента, по ним логика перезапуска (start_and_restart_ws_client) решает, есть ли смысл переподключаться
+#[derive(Debug, thiserror::Error)]
+pub enum WsError {
+    /// Сервер отклонил токен (401, 403), повторные попытки ничего не дадут
+    #[error("authorization rejected, HTTP status: {0}")]
+    Auth(StatusCode),
+    #[error("unexpected HTTP status: {0}")]
+    Http(StatusCode),
+    /// Некорректный запрос на подключение (например, неверный url в конфиге), повторные попытки ничего не дадут
+    #[error("invalid request: {0}")]
+    Request(String),
+    /// DNS, TCP и прочие ошибки ввода-вывода
+    #[error("network error: {0}")]
+    Network(#[from] std::io::Error),
+    #[error("TLS error: {0}")]
+    Tls(String),
+    #[error("protocol error: {0}")]
+    Protocol(String),
+    #[error("server closed the connection, code: {code}, reason: {reason}")]
+    Closed { code: u16, reason: String },
+    #[error("stream ended")]
+    Ended,
+    /// Соединение формально открыто, но данные не приходят (см. client::ws::watchdog)
+    #[error("feed is stale: {0}")]
+    Stale(String),
+    #[error("internal error: {0}")]
+    Internal(String),
+}
+
+impl WsError {
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, WsError::Auth(_) | WsError::Request(_))
+    }
+
+    pub fn closed(frame: Option<CloseFrame>) -> Self {
+        match frame {
+            Some(frame) => WsError::Closed {
+                code: frame.code.into(),
+                reason: frame.reason.to_string(),
+            },
+            // 1005 - код закрытия не передан (https://tools.ietf.org/html/rfc6455#section-7.4.1)
+            None => WsError::Closed {
+                code: 1005,
+                reason: String::new(),
+            },
+        }
+    }
+}
+
+// Возможные ошибки: https://docs.rs/tungstenite/0.13.0/tungstenite/error/enum.Error.html
+impl From<TungsteniteError> for WsError {
+    fn from(err: TungsteniteError) -> Self {
+        match err {
+            TungsteniteError::Http(response) => match response.status() {
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => WsError::Auth(response.status()),
+                status => WsError::Http(status),
+            },
+            TungsteniteError::Io(err) => WsError::Network(err),
+            TungsteniteError::Tls(err) => WsError::Tls(err.to_string()),
+            TungsteniteError::Url(err) => WsError::Request(err.to_string()),
+            TungsteniteError::HttpFormat(err) => WsError::Request(err.to_string()),
+            TungsteniteError::ConnectionClosed | TungsteniteError::AlreadyClosed => WsError::Ended,
+            err => WsError::Protocol(err.to_string()),
+        }
+    }
+}
+
+impl From<tungstenite::http::Error> for WsError {
+    fn from(err: tungstenite::http::Error) -> Self {
+        WsError::Request(err.to_string())
+    }
+}
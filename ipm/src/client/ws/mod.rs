@@ -1,53 +1,105 @@
-use anyhow::anyhow;
 use chrono::{DateTime, Utc};
+use futures::stream::SplitSink;
 use futures::{SinkExt, StreamExt};
 use log::{debug, error, info};
 use serde::Deserialize;
 use tokio::net::TcpStream;
 use tokio::sync::broadcast;
+use tokio::time;
 use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
 use tokio_util::sync::CancellationToken;
 use tungstenite::{handshake::client::Request, Message};
 
-use crate::domain::{order_book::OrderBook, trade::Trade};
-use crate::settings::Tinkoff;
+use crate::client::reconnect::ConnectionMonitor;
+use crate::domain::{candle::Candle, order_book::OrderBook, trade::Trade};
+use crate::settings::{Instrument, Tinkoff};
+use crate::subscriptions::{Change, Subscriptions};
+use shared::channel::BroadcastSender;
 
-use candle::{CandlePayload, SubscribeCandleReq};
-use order_book::{OrderBookPayload, SubscribeOrderBookReq};
+use candle::{CandlePayload, CandleReq};
+pub use error::WsError;
+use order_book::{OrderBookPayload, OrderBookReq};
+use recorder::Recorder;
+use watchdog::Watchdog;
 
 mod candle;
 pub mod emulator;
+mod error;
 mod order_book;
+pub mod recorder;
+pub mod replay;
+mod watchdog;
 
 pub async fn run(
     cfg: Tinkoff,
-    trade_sender: broadcast::Sender<Trade>,
-    order_book_sender: broadcast::Sender<OrderBook>,
+    subscriptions: Subscriptions,
+    monitor: ConnectionMonitor,
+    trade_sender: BroadcastSender<Trade>,
+    order_book_sender: BroadcastSender<OrderBook>,
+    candle_sender: BroadcastSender<Candle>,
     shutdown: CancellationToken,
-) -> anyhow::Result<()> {
-    let stream = prepare_web_socket(cfg).await?;
-    reading(stream, trade_sender, order_book_sender, shutdown).await
+) -> Result<(), WsError> {
+    // Сначала подписываемся на изменения и только потом берем актуальный набор инструментов, так ничего не потеряем
+    let changes = subscriptions.changes();
+    let figis = subscriptions.figis();
+    let stream = prepare_web_socket(&cfg, &figis).await?;
+    monitor.connected();
+
+    let watchdog = Watchdog::new(cfg.heartbeat.clone(), &figis);
+    let senders = Senders {
+        trade: trade_sender,
+        order_book: order_book_sender,
+        candle: candle_sender,
+    };
+    let recorder = start_recorder(&cfg);
+    reading(stream, cfg, watchdog, changes, senders, recorder, shutdown).await
+}
+
+/// Ошибка записи не должна мешать получению данных, поэтому в этом случае работаем без записи
+fn start_recorder(cfg: &Tinkoff) -> Option<Recorder> {
+    if !cfg.recorder.enabled {
+        return None;
+    }
+
+    match Recorder::start(cfg.recorder.clone()) {
+        Ok(recorder) => Some(recorder),
+        Err(err) => {
+            error!(
+                "could not start ws recorder, frames will not be recorded, error: {}",
+                err
+            );
+            None
+        }
+    }
+}
+
+/// Сендеры, через которые данные, вычитанные из ws, транслируются дальше
+struct Senders {
+    trade: BroadcastSender<Trade>,
+    order_book: BroadcastSender<OrderBook>,
+    candle: BroadcastSender<Candle>,
 }
 
 type WebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
 
-async fn prepare_web_socket(cfg: Tinkoff) -> anyhow::Result<WebSocket> {
+async fn prepare_web_socket(cfg: &Tinkoff, figis: &[String]) -> Result<WebSocket, WsError> {
     let request = Request::builder()
         .method("GET")
         .uri(cfg.ws.clone())
         .header("Authorization", format!("Bearer {}", cfg.token.clone()))
         .body(())?;
 
-    let (mut stream, response) = connect_async(request).await.expect("failed to ws connect");
+    let (mut stream, response) = connect_async(request).await?;
 
     info!("connected to the server, response HTTP code: {}", response.status());
 
-    for figi in cfg.figis.iter() {
-        stream.send(prepare_subscribe_candle_req(figi)).await?;
-        stream.send(prepare_subscribe_order_book_req(figi)).await?;
+    for figi in figis.iter() {
+        for msg in prepare_subscribe_reqs(&cfg.instrument(figi)) {
+            stream.send(msg).await?;
+        }
     }
 
-    anyhow::Result::Ok(stream)
+    Ok(stream)
 }
 
 fn extract_message(msg: Message) -> Option<StreamMsg> {
@@ -71,23 +123,36 @@ fn extract_message(msg: Message) -> Option<StreamMsg> {
 }
 
 async fn reading(
-    mut stream: WebSocket,
-    trade_sender: broadcast::Sender<Trade>,
-    order_book_sender: broadcast::Sender<OrderBook>,
+    stream: WebSocket,
+    cfg: Tinkoff,
+    mut watchdog: Watchdog,
+    mut changes: broadcast::Receiver<Change>,
+    senders: Senders,
+    recorder: Option<Recorder>,
     shutdown: CancellationToken,
-) -> anyhow::Result<()> {
-    let result = tokio::spawn(async move {
+) -> Result<(), WsError> {
+    let (mut sink, mut stream) = stream.split();
+    let mut heartbeat = time::interval(time::Duration::from_secs(cfg.heartbeat.ping_interval_secs));
+    tokio::spawn(async move {
         loop {
-            let result: Option<anyhow::Result<()>> = tokio::select! {
+            let result: Option<Result<(), WsError>> = tokio::select! {
                 val = stream.next() => {
                     match val {
-                        Some(item) => process_new_item(item, trade_sender.clone(), order_book_sender.clone()),
-                        None => None,
+                        Some(item) => process_new_item(item, Some(&mut watchdog), &senders, recorder.as_ref()),
+                        None => Some(Err(WsError::Ended)), // Соединение закрыто без close фрейма, переподключаемся
+                    }
+                },
+                val = changes.recv() => {
+                    match val {
+                        Ok(change) => apply_change(&mut sink, &cfg, &mut watchdog, change).await,
+                        // Пропустили изменения, переподключаемся - после переподключения будет взят актуальный набор
+                        Err(err) => Some(Err(WsError::Internal(format!("subscriptions changes receiving failed: {}", err)))),
                     }
                 },
+                _ = heartbeat.tick() => ping(&mut sink, &mut watchdog).await,
                 _ = shutdown.cancelled() => {
                     info!("ws client finished");
-                    Some(anyhow::Result::Ok(()))
+                    Some(Ok(()))
                 }
             };
 
@@ -97,29 +162,36 @@ async fn reading(
         }
     })
     .await
-    .unwrap();
-
-    result
+    .unwrap_or_else(|err| Err(WsError::Internal(format!("ws reading task failed: {}", err))))
 }
 
 fn process_new_item(
     item: Result<Message, tungstenite::error::Error>,
-    trade_sender: broadcast::Sender<Trade>,
-    order_book_sender: broadcast::Sender<OrderBook>,
-) -> Option<anyhow::Result<()>> {
-    // Возможные ошибки: https://docs.rs/tungstenite/0.13.0/tungstenite/error/enum.Error.html
-    if let Err(err) = item {
-        log::error!("streaming error: {:?}", err);
-        return Some(Err(anyhow!("streaming error: {:?}", err))); // Выходим с ошибкой, это приведет к переподключению (start_and_restart_ws_client)
+    mut watchdog: Option<&mut Watchdog>,
+    senders: &Senders,
+    recorder: Option<&Recorder>,
+) -> Option<Result<(), WsError>> {
+    let message = match item {
+        Ok(message) => message,
+        Err(err) => {
+            log::error!("streaming error: {:?}", err);
+            return Some(Err(WsError::from(err))); // Выходим с ошибкой, это приведет к переподключению (start_and_restart_ws_client)
+        }
+    };
+
+    if let Some(recorder) = recorder {
+        recorder.record(&message);
     }
 
-    // Возможные типы сообщения: https://docs.rs/tungstenite/0.13.0/tungstenite/enum.Message.html
-    let message = item.unwrap();
+    // Любое сообщение, в том числе pong, говорит о том, что соединение живое
+    if let Some(watchdog) = watchdog.as_mut() {
+        watchdog.alive();
+    }
 
-    if message.is_close() {
-        log::warn!("stream closed");
-        let e = anyhow::Error::msg("stream closed");
-        return Some(anyhow::Result::Err(e)); // Выходим с ошибкой, это приведет к переподключению (start_and_restart_ws_client)
+    // Возможные типы сообщения: https://docs.rs/tungstenite/0.13.0/tungstenite/enum.Message.html
+    if let Message::Close(frame) = message {
+        log::warn!("stream closed: {:?}", frame);
+        return Some(Err(WsError::closed(frame))); // Выходим с ошибкой, это приведет к переподключению (start_and_restart_ws_client)
     }
 
     if !message.is_text() {
@@ -131,26 +203,60 @@ fn process_new_item(
             StreamMsg::Candle { payload, time } => {
                 debug!("payload: {:?}, time: {}, now: {}", payload, time, Utc::now());
 
-                if trade_sender.receiver_count() == 0 {
-                    return None; // Отправлять некому, поэтому досрочный выход
+                if let Some(watchdog) = watchdog.as_mut() {
+                    watchdog.seen(&payload.figi);
                 }
 
-                let trade = Trade::new(payload.c, payload.v, payload.figi, payload.time, time, Utc::now());
-
+                let received = Utc::now();
                 debug!(
                     "it is difference between sent and received: {} (ms)",
-                    trade.received.signed_duration_since(trade.sent).num_milliseconds()
+                    received.signed_duration_since(time).num_milliseconds()
                 );
 
-                if let Err(err) = trade_sender.send(trade) {
-                    error!("send trade failed, error: {}", err);
+                // Из свечи получаем два потока: trade (цена закрытия и объем) и полноценная свеча (OHLC)
+                if senders.trade.receiver_count() > 0 {
+                    let trade = Trade::new(
+                        payload.c,
+                        payload.v,
+                        payload.figi.clone(),
+                        payload.interval.clone(),
+                        payload.time,
+                        time,
+                        received,
+                    );
+
+                    if let Err(err) = senders.trade.send(trade) {
+                        error!("send trade failed, error: {}", err);
+                    }
+                }
+
+                if senders.candle.receiver_count() > 0 {
+                    let candle = Candle {
+                        figi: payload.figi,
+                        interval: payload.interval,
+                        open: payload.o,
+                        close: payload.c,
+                        high: payload.h,
+                        low: payload.l,
+                        volume: payload.v,
+                        time: payload.time,
+                        sent: time,
+                        received,
+                    };
+
+                    if let Err(err) = senders.candle.send(candle) {
+                        error!("send candle failed, error: {}", err);
+                    }
                 }
             }
 
             StreamMsg::OrderBook { payload, time } => {
                 debug!("payload: {:?}, time: {}", payload, time);
+                if let Some(watchdog) = watchdog.as_mut() {
+                    watchdog.seen(&payload.figi);
+                }
 
-                if order_book_sender.receiver_count() == 0 {
+                if senders.order_book.receiver_count() == 0 {
                     return None; // Отправлять некому, поэтому досрочный выход
                 }
 
@@ -171,24 +277,101 @@ fn process_new_item(
                         .num_milliseconds()
                 );
 
-                if let Err(err) = order_book_sender.send(order_book) {
+                if let Err(err) = senders.order_book.send(order_book) {
                     error!("send order book failed, error: {}", err);
                 }
             }
+
+            StreamMsg::Error { payload, time } => {
+                error!(
+                    "tinkoff error: {}, request id: {:?}, time: {}",
+                    payload.error, payload.request_id, time
+                );
+            }
+        }
+    }
+
+    None
+}
+
+/// Применяет изменение набора инструментов к живому ws соединению
+async fn apply_change(
+    sink: &mut SplitSink<WebSocket, Message>,
+    cfg: &Tinkoff,
+    watchdog: &mut Watchdog,
+    change: Change,
+) -> Option<Result<(), WsError>> {
+    info!("subscriptions changed: {:?}", change);
+
+    let messages = match change {
+        Change::Subscribe(figi) => {
+            watchdog.subscribed(&figi);
+            prepare_subscribe_reqs(&cfg.instrument(&figi))
+        }
+        Change::Unsubscribe(figi) => {
+            watchdog.unsubscribed(&figi);
+            prepare_unsubscribe_reqs(&cfg.instrument(&figi))
+        }
+    };
+
+    for msg in messages {
+        if let Err(err) = sink.send(msg).await {
+            // Выходим с ошибкой, это приведет к переподключению (start_and_restart_ws_client)
+            error!("sending subscriptions change failed: {:?}", err);
+            return Some(Err(WsError::from(err)));
         }
     }
 
     None
 }
 
-fn prepare_subscribe_candle_req(figi: &str) -> Message {
-    let req = SubscribeCandleReq::prepare(figi.to_string());
+/// Проверяет, что поток данных не "завис", и отправляет ping, ответный pong обновит время последнего сообщения
+async fn ping(sink: &mut SplitSink<WebSocket, Message>, watchdog: &mut Watchdog) -> Option<Result<(), WsError>> {
+    if let Some(reason) = watchdog.check(Utc::now()) {
+        // Выходим с ошибкой, это приведет к переподключению (start_and_restart_ws_client)
+        error!("ws feed is stale, forcing reconnect: {}", reason);
+        return Some(Err(WsError::Stale(reason)));
+    }
+
+    if let Err(err) = sink.send(Message::Ping(Vec::new())).await {
+        error!("sending ping failed: {:?}", err);
+        return Some(Err(WsError::from(err)));
+    }
+
+    None
+}
+
+/// Подписка на свечи по каждому из интервалов инструмента и на книгу заказов с заданной глубиной
+fn prepare_subscribe_reqs(instrument: &Instrument) -> Vec<Message> {
+    let mut messages: Vec<_> = instrument
+        .candle_intervals
+        .iter()
+        .map(|i| prepare_candle_req(CandleReq::subscribe(instrument.figi.clone(), i.clone())))
+        .collect();
+
+    let req = OrderBookReq::subscribe(instrument.figi.clone(), instrument.order_book_depth);
+    messages.push(prepare_order_book_req(req));
+    messages
+}
+
+fn prepare_unsubscribe_reqs(instrument: &Instrument) -> Vec<Message> {
+    let mut messages: Vec<_> = instrument
+        .candle_intervals
+        .iter()
+        .map(|i| prepare_candle_req(CandleReq::unsubscribe(instrument.figi.clone(), i.clone())))
+        .collect();
+
+    let req = OrderBookReq::unsubscribe(instrument.figi.clone(), instrument.order_book_depth);
+    messages.push(prepare_order_book_req(req));
+    messages
+}
+
+fn prepare_candle_req(req: CandleReq) -> Message {
     let msg = serde_json::to_string(&req).unwrap();
     Message::Text(msg)
 }
 
-fn prepare_subscribe_order_book_req(figi: &str) -> Message {
-    let req = SubscribeOrderBookReq::prepare(figi.to_string());
+fn prepare_order_book_req(req: OrderBookReq) -> Message {
     let msg = serde_json::to_string(&req).unwrap();
     Message::Text(msg)
 }
@@ -207,4 +390,13 @@ enum StreamMsg {
         payload: OrderBookPayload,
         time: DateTime<Utc>,
     },
+    // Например, ответ на запрос с неверными параметрами или на отписку от несуществующей подписки
+    #[serde(rename = "error")]
+    Error { payload: ErrorPayload, time: DateTime<Utc> },
+}
+
+#[derive(Deserialize, Debug)]
+struct ErrorPayload {
+    error: String,
+    request_id: Option<String>,
 }
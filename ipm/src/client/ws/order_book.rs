@@ -1,20 +1,26 @@
 use serde::{Deserialize, Serialize};
 
-const ORDER_BOOK_DEPTH: u32 = 10;
-
 #[derive(Serialize, Debug)]
-pub struct SubscribeOrderBookReq {
+pub struct OrderBookReq {
     event: String,
     figi: String,
     depth: u32,
 }
 
-impl SubscribeOrderBookReq {
-    pub fn prepare(figi: String) -> SubscribeOrderBookReq {
-        SubscribeOrderBookReq {
-            event: "orderbook:subscribe".to_string(),
+impl OrderBookReq {
+    pub fn subscribe(figi: String, depth: u32) -> OrderBookReq {
+        OrderBookReq::prepare("orderbook:subscribe", figi, depth)
+    }
+
+    pub fn unsubscribe(figi: String, depth: u32) -> OrderBookReq {
+        OrderBookReq::prepare("orderbook:unsubscribe", figi, depth)
+    }
+
+    fn prepare(event: &str, figi: String, depth: u32) -> OrderBookReq {
+        OrderBookReq {
+            event: event.to_string(),
             figi,
-            depth: ORDER_BOOK_DEPTH,
+            depth,
         }
     }
 }
@@ -0,0 +1,210 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, TrySendError};
+use std::thread;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use flate2::{write::GzEncoder, Compression};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
+use tungstenite::Message;
+
+use crate::settings::Recorder as RecorderCfg;
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// Сколько фреймов может ждать записи, если сжатие или диск не успевают (например, в начале торгов)
+const QUEUE_CAPACITY: usize = 10_000;
+
+/// Запись в исходном виде, так, как она пришла по ws.
+/// Текстовые фреймы пишутся как есть, бинарные (и payload у ping/pong) - в base64, close - как "<код> <причина>".
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Record {
+    pub received: DateTime<Utc>,
+    pub kind: String,
+    pub data: String,
+}
+
+impl Record {
+    fn new(msg: &Message, received: DateTime<Utc>) -> Self {
+        let (kind, data) = match msg {
+            Message::Text(text) => ("text", text.clone()),
+            Message::Binary(data) => ("binary", base64::encode(data)),
+            Message::Ping(data) => ("ping", base64::encode(data)),
+            Message::Pong(data) => ("pong", base64::encode(data)),
+            Message::Close(frame) => (
+                "close",
+                frame
+                    .as_ref()
+                    .map(|f| format!("{} {}", u16::from(f.code), f.reason))
+                    .unwrap_or_default(),
+            ),
+        };
+
+        Record {
+            received,
+            kind: kind.to_string(),
+            data,
+        }
+    }
+
+    /// Обратное преобразование, используется при воспроизведении записей (client::ws::replay)
+    pub fn into_message(self) -> anyhow::Result<Message> {
+        let msg = match self.kind.as_str() {
+            "text" => Message::Text(self.data),
+            "binary" => Message::Binary(base64::decode(self.data)?),
+            "ping" => Message::Ping(base64::decode(self.data)?),
+            "pong" => Message::Pong(base64::decode(self.data)?),
+            "close" if self.data.is_empty() => Message::Close(None),
+            "close" => {
+                let (code, reason) = self.data.split_once(' ').unwrap_or((&self.data, ""));
+                Message::Close(Some(CloseFrame {
+                    code: CloseCode::from(code.parse::<u16>()?),
+                    reason: reason.to_string().into(),
+                }))
+            }
+            kind => anyhow::bail!("unknown record kind: {}", kind),
+        };
+
+        Ok(msg)
+    }
+}
+
+/// Пишет все входящие ws фреймы в сжатые (gzip) файлы, по одной json записи на строку.
+/// Нужен, чтобы постфактум разбираться, что именно прислал Тинькофф (например, при "unknown json format"),
+/// независимо от того, что распарсили и сохранили в pr.
+/// Запись идет в отдельном потоке, чтобы не тормозить чтение ws. Файл ротируется по времени и по размеру,
+/// для каждого подключения создается новый файл. Когда Recorder удаляется, текущий файл закрывается.
+/// Очередь на запись ограничена QUEUE_CAPACITY, при переполнении фреймы не записываются (счетчик dropped).
+pub struct Recorder {
+    sender: mpsc::SyncSender<Record>,
+    dropped: AtomicU64,
+}
+
+impl Recorder {
+    pub fn start(cfg: RecorderCfg) -> anyhow::Result<Self> {
+        fs::create_dir_all(&cfg.dir)?;
+
+        let (sender, receiver) = mpsc::sync_channel::<Record>(QUEUE_CAPACITY);
+        let mut writer = RotatingWriter::new(cfg);
+
+        thread::spawn(move || {
+            loop {
+                let res = match receiver.recv_timeout(FLUSH_INTERVAL) {
+                    Ok(record) => writer.write(&record),
+                    Err(RecvTimeoutError::Timeout) => writer.flush(),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+
+                if let Err(err) = res {
+                    error!("recording ws frame failed, error: {}", err);
+                    writer.reset();
+                }
+            }
+
+            if let Err(err) = writer.close() {
+                error!("closing recorder file failed, error: {}", err);
+            }
+        });
+
+        Ok(Recorder {
+            sender,
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    pub fn record(&self, msg: &Message) {
+        match self.sender.try_send(Record::new(msg, Utc::now())) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                if dropped % 1000 == 1 {
+                    warn!(
+                        "recorder queue is full ({} frames), dropped: {}",
+                        QUEUE_CAPACITY, dropped
+                    );
+                }
+            }
+            // Поток записи завершился, об этом он уже сообщил в лог
+            Err(TrySendError::Disconnected(_)) => {}
+        }
+    }
+}
+
+struct RotatingWriter {
+    cfg: RecorderCfg,
+    file: Option<(GzEncoder<BufWriter<File>>, DateTime<Utc>)>,
+    written: u64,
+}
+
+impl RotatingWriter {
+    fn new(cfg: RecorderCfg) -> Self {
+        RotatingWriter {
+            cfg,
+            file: None,
+            written: 0,
+        }
+    }
+
+    fn write(&mut self, record: &Record) -> anyhow::Result<()> {
+        let now = Utc::now();
+        if self.need_rotate(now) {
+            self.close()?;
+        }
+
+        if self.file.is_none() {
+            let path = file_path(&self.cfg.dir, now);
+            info!("recording ws frames to {}", path.display());
+            let file = BufWriter::new(File::create(path)?);
+            self.file = Some((GzEncoder::new(file, Compression::default()), now));
+        }
+
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+
+        let (encoder, _) = self.file.as_mut().unwrap();
+        encoder.write_all(&line)?;
+        self.written += line.len() as u64;
+
+        Ok(())
+    }
+
+    /// Сброс сжатого буфера на диск, чтобы при аварийном завершении терялось не больше FLUSH_INTERVAL
+    fn flush(&mut self) -> anyhow::Result<()> {
+        if let Some((encoder, _)) = self.file.as_mut() {
+            encoder.flush()?;
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) -> anyhow::Result<()> {
+        self.written = 0;
+        if let Some((encoder, _)) = self.file.take() {
+            encoder.finish()?.flush()?;
+        }
+        Ok(())
+    }
+
+    /// После ошибки файл бросаем (он может быть поврежден), следующая запись начнет новый
+    fn reset(&mut self) {
+        self.file = None;
+        self.written = 0;
+    }
+
+    fn need_rotate(&self, now: DateTime<Utc>) -> bool {
+        match &self.file {
+            Some((_, opened)) => {
+                now.signed_duration_since(*opened).num_seconds() >= self.cfg.rotate_secs as i64
+                    || self.written >= self.cfg.max_file_size_mb * 1024 * 1024
+            }
+            None => false,
+        }
+    }
+}
+
+fn file_path(dir: &str, now: DateTime<Utc>) -> PathBuf {
+    Path::new(dir).join(format!("ws-{}.ndjson.gz", now.format("%Y%m%d-%H%M%S%.3f")))
+}
@@ -0,0 +1,229 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use log::{error, info, warn};
+use tokio::sync::mpsc;
+use tokio::time;
+use tokio_util::sync::CancellationToken;
+
+use crate::domain::{candle::Candle, order_book::OrderBook, trade::Trade};
+use shared::channel::BroadcastSender;
+
+use super::recorder::Record;
+use super::{process_new_item, Senders};
+
+/// Сколько прочитанных записей может ждать воспроизведения
+const READ_AHEAD: usize = 1000;
+
+/// Скорость воспроизведения: Factor(1.0) - как в записи, Factor(N) - в N раз быстрее, Max - без пауз
+#[derive(Debug, Clone, Copy)]
+pub enum Speed {
+    Factor(f64),
+    Max,
+}
+
+impl FromStr for Speed {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "max" {
+            return Ok(Speed::Max);
+        }
+
+        match s.parse::<f64>()? {
+            factor if factor > 0.0 => Ok(Speed::Factor(factor)),
+            _ => anyhow::bail!("replay speed must be greater than 0 or \"max\""),
+        }
+    }
+}
+
+/// Третий источник данных (наряду с ws и emulator): воспроизводит сырые ws фреймы, записанные client::ws::recorder.
+/// Фреймы проходят тот же путь разбора (process_new_item), что и при работе с Тинькофф, поэтому так можно
+/// воспроизводить ошибки разбора и проверять ipm без токена и сети.
+/// path - файл или каталог с файлами *.ndjson.gz, файлы воспроизводятся в порядке имен (т.е. по времени создания).
+/// Файлы читаются и распаковываются в отдельном (blocking) потоке, записи передаются через ограниченную очередь.
+/// При скорости Max паузы нет, и потребители broadcast каналов могут не успевать: потерянные ими сообщения (lagged)
+/// пишутся в лог, для регрессионных проверок лучше задавать конечную скорость.
+pub async fn run(
+    path: &str,
+    speed: Speed,
+    trade_sender: BroadcastSender<Trade>,
+    order_book_sender: BroadcastSender<OrderBook>,
+    candle_sender: BroadcastSender<Candle>,
+    shutdown: CancellationToken,
+) -> anyhow::Result<()> {
+    let files = collect_files(Path::new(path))?;
+    if files.is_empty() {
+        anyhow::bail!("no records found in {}", path);
+    }
+
+    let senders = Senders {
+        trade: trade_sender,
+        order_book: order_book_sender,
+        candle: candle_sender,
+    };
+
+    let (sender, mut receiver) = mpsc::channel::<Frame>(READ_AHEAD);
+    tokio::task::spawn_blocking(move || read_files(files, sender));
+
+    tokio::spawn(async move {
+        let mut player = Player::new(speed, senders);
+        loop {
+            tokio::select! {
+                frame = receiver.recv() => match frame {
+                    Some(frame) => player.play(frame).await,
+                    None => break,
+                },
+                _ = shutdown.cancelled() => return,
+            }
+        }
+        player.report_lagged();
+        info!("ws records replay finished");
+    });
+
+    Ok(())
+}
+
+fn collect_files(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    if path.is_file() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut files: Vec<_> = fs::read_dir(path)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| p.to_string_lossy().ends_with(".ndjson.gz"))
+        .collect();
+    files.sort();
+
+    Ok(files)
+}
+
+enum Frame {
+    /// Начало очередного файла
+    File(PathBuf),
+    /// Запись и ее место в файле (для сообщений об ошибках)
+    Record { at: String, record: Record },
+}
+
+/// Выполняется в blocking потоке, завершается, когда файлы закончились или воспроизведение остановлено
+fn read_files(files: Vec<PathBuf>, sender: mpsc::Sender<Frame>) {
+    for file in files {
+        if sender.blocking_send(Frame::File(file.clone())).is_err() {
+            return;
+        }
+
+        if let Err(err) = read_file(&file, &sender) {
+            error!("replaying {} failed, error: {}", file.display(), err);
+        }
+    }
+}
+
+fn read_file(path: &Path, sender: &mpsc::Sender<Frame>) -> anyhow::Result<()> {
+    let reader = BufReader::new(GzDecoder::new(File::open(path)?));
+
+    for (n, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+
+        let at = format!("{}:{}", path.display(), n + 1);
+        let record: Record = match serde_json::from_str(&line) {
+            Ok(record) => record,
+            Err(err) => {
+                warn!("skip broken record at {}, error: {}", at, err);
+                continue;
+            }
+        };
+
+        if sender.blocking_send(Frame::Record { at, record }).is_err() {
+            return Ok(()); // воспроизведение остановлено
+        }
+    }
+
+    Ok(())
+}
+
+struct Player {
+    speed: Speed,
+    senders: Senders,
+    prev: Option<DateTime<Utc>>,
+    lagged: u64,
+}
+
+impl Player {
+    fn new(speed: Speed, senders: Senders) -> Self {
+        let lagged = lagged(&senders);
+        Player {
+            speed,
+            senders,
+            prev: None,
+            lagged,
+        }
+    }
+
+    async fn play(&mut self, frame: Frame) {
+        let (at, record) = match frame {
+            Frame::File(file) => {
+                self.report_lagged();
+                info!("replaying ws records from {}", file.display());
+                self.prev = None;
+                return;
+            }
+            Frame::Record { at, record } => (at, record),
+        };
+
+        // Выдерживаем паузы между фреймами так, как они были получены при записи
+        match self.speed {
+            Speed::Factor(factor) => {
+                if let Some(prev) = self.prev {
+                    let pause = record
+                        .received
+                        .signed_duration_since(prev)
+                        .num_microseconds()
+                        .unwrap_or(0);
+                    if pause > 0 {
+                        time::sleep(time::Duration::from_micros((pause as f64 / factor) as u64)).await;
+                    }
+                }
+                self.prev = Some(record.received);
+            }
+            // Даем поработать другим задачам, иначе при максимальной скорости поток будет занят только воспроизведением
+            Speed::Max => tokio::task::yield_now().await,
+        }
+
+        let message = match record.into_message() {
+            Ok(message) => message,
+            Err(err) => {
+                warn!("skip broken record at {}, error: {}", at, err);
+                return;
+            }
+        };
+
+        // Ошибки (например, close фрейм) только логируем, переподключаться здесь некуда
+        if let Some(Err(err)) = process_new_item(Ok(message), None, &self.senders, None) {
+            warn!("replayed frame at {} ends the connection: {}", at, err);
+        }
+    }
+
+    /// Потребители, которые не успевают читать, теряют сообщения, воспроизведение в этом случае неполное
+    fn report_lagged(&mut self) {
+        let lagged = lagged(&self.senders);
+        if lagged > self.lagged {
+            warn!(
+                "consumers lost {} replayed messages (lagged), total: {}, set a lower replay speed",
+                lagged - self.lagged,
+                lagged
+            );
+            self.lagged = lagged;
+        }
+    }
+}
+
+fn lagged(senders: &Senders) -> u64 {
+    senders.trade.stats().lagged() + senders.order_book.stats().lagged() + senders.candle.stats().lagged()
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Datelike, Duration, Utc, Weekday};
+
+use crate::settings::Heartbeat;
+
+/// Следит за тем, что ws соединение живое.
+/// Полуоткрытое TCP соединение не дает ни ошибок, ни close фрейма, данные просто перестают приходить,
+/// поэтому периодически отправляем ping и проверяем время последнего сообщения (pong тоже считается).
+/// Дополнительно в торговые часы проверяем время последнего сообщения по каждому инструменту:
+/// соединение может быть живым, а данные по подписке не приходить.
+pub struct Watchdog {
+    cfg: Heartbeat,
+    last_message: DateTime<Utc>,
+    figis: HashMap<String, DateTime<Utc>>,
+}
+
+impl Watchdog {
+    pub fn new(cfg: Heartbeat, figis: &[String]) -> Self {
+        let now = Utc::now();
+        Watchdog {
+            cfg,
+            last_message: now,
+            figis: figis.iter().map(|f| (f.clone(), now)).collect(),
+        }
+    }
+
+    /// Любое сообщение от сервера, включая pong
+    pub fn alive(&mut self) {
+        self.last_message = Utc::now();
+    }
+
+    /// Пришли данные по инструменту
+    pub fn seen(&mut self, figi: &str) {
+        if let Some(last) = self.figis.get_mut(figi) {
+            *last = Utc::now();
+        }
+    }
+
+    /// Отсчет для нового инструмента начинается с момента подписки
+    pub fn subscribed(&mut self, figi: &str) {
+        self.figis.insert(figi.to_string(), Utc::now());
+    }
+
+    pub fn unsubscribed(&mut self, figi: &str) {
+        self.figis.remove(figi);
+    }
+
+    /// Возвращает причину, если поток данных "завис" и нужно переподключиться
+    pub fn check(&mut self, now: DateTime<Utc>) -> Option<String> {
+        let silence = now.signed_duration_since(self.last_message);
+        if silence > Duration::seconds(self.cfg.timeout_secs as i64) {
+            return Some(format!(
+                "no messages (including pong) for {} s, connection is considered dead",
+                silence.num_seconds()
+            ));
+        }
+
+        if self.cfg.figi_stale_secs == 0 {
+            return None;
+        }
+
+        // Вне торговых часов отсутствие данных - это нормально, поэтому сдвигаем отсчет,
+        // чтобы после открытия торгов у инструментов было полное время на первое сообщение
+        if !self.is_trading_hours(now) {
+            self.figis.values_mut().for_each(|last| *last = now);
+            return None;
+        }
+
+        let stale = Duration::seconds(self.cfg.figi_stale_secs as i64);
+        self.figis
+            .iter()
+            .find(|(_, last)| now.signed_duration_since(**last) > stale)
+            .map(|(figi, last)| {
+                format!(
+                    "no data for {} during {} s of trading hours",
+                    figi,
+                    now.signed_duration_since(*last).num_seconds()
+                )
+            })
+    }
+
+    /// Торговые часы задаются в UTC и действуют по будним дням, интервал может переходить через полночь.
+    /// Будний ли день - определяется по дню начала сессии: часы после полуночи относятся к сессии предыдущего дня.
+    fn is_trading_hours(&self, now: DateTime<Utc>) -> bool {
+        let time = now.time();
+        let (start, end) = (self.cfg.trading_hours.start, self.cfg.trading_hours.end);
+        let session_day = match start <= end {
+            true if start <= time && time < end => now,
+            false if start <= time => now,
+            false if time < end => now - Duration::days(1),
+            _ => return false,
+        };
+
+        !matches!(session_day.weekday(), Weekday::Sat | Weekday::Sun)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveTime, TimeZone};
+
+    use super::*;
+    use crate::settings::TradingHours;
+
+    fn watchdog(start: (u32, u32), end: (u32, u32)) -> Watchdog {
+        let cfg = Heartbeat {
+            ping_interval_secs: 1,
+            timeout_secs: 2,
+            figi_stale_secs: 60,
+            trading_hours: TradingHours {
+                start: NaiveTime::from_hms(start.0, start.1, 0),
+                end: NaiveTime::from_hms(end.0, end.1, 0),
+            },
+        };
+        Watchdog::new(cfg, &[])
+    }
+
+    // 2021-10-22 - пятница, 2021-10-24 - воскресенье, 2021-10-25 - понедельник
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        Utc.ymd(2021, 10, day).and_hms(hour, 0, 0)
+    }
+
+    #[test]
+    fn weekdays_within_day() {
+        let w = watchdog((7, 0), (16, 0));
+        assert!(w.is_trading_hours(at(22, 10)));
+        assert!(!w.is_trading_hours(at(22, 17)));
+        assert!(!w.is_trading_hours(at(23, 10)));
+        assert!(!w.is_trading_hours(at(24, 10)));
+        assert!(w.is_trading_hours(at(25, 10)));
+    }
+
+    #[test]
+    fn session_over_midnight_belongs_to_its_start_day() {
+        let w = watchdog((22, 0), (2, 0));
+        // хвост пятничной сессии в ночь на субботу
+        assert!(w.is_trading_hours(at(23, 1)));
+        // сессия не начинается в субботу и воскресенье
+        assert!(!w.is_trading_hours(at(23, 23)));
+        assert!(!w.is_trading_hours(at(24, 23)));
+        // ночь на понедельник - хвост воскресной сессии, которой нет
+        assert!(!w.is_trading_hours(at(25, 1)));
+        assert!(w.is_trading_hours(at(25, 23)));
+        assert!(!w.is_trading_hours(at(25, 12)));
+    }
+}
@@ -1,4 +1,3 @@
-#[macro_use]
 macro_rules! order_book_from {
     ($p: path) => {
         type DomainOrderBook = crate::domain::order_book::OrderBook;
@@ -30,6 +29,7 @@ macro_rules! order_book_from {
                         asks,
                         sent: item.sent.timestamp_millis(),
                         received: item.received.timestamp_millis(),
+                        dropped: 0,
                     }
                 }
             }
@@ -47,9 +47,36 @@ macro_rules! trade_from {
                         price: item.price,
                         volume: item.volume,
                         figi: item.figi,
+                        interval: item.interval,
                         minute_rounded: item.minute_rounded.timestamp_millis(),
                         sent: item.sent.timestamp_millis(),
                         received: item.received.timestamp_millis(),
+                        dropped: 0,
+                    }
+                }
+            }
+        }
+    };
+}
+
+macro_rules! candle_from {
+    ($p: path) => {
+        type DomainCandle = crate::domain::candle::Candle;
+        paste::paste! {
+            impl From<DomainCandle> for [<$p>]::Candle {
+                fn from(item: DomainCandle) -> Self {
+                    Self {
+                        figi: item.figi,
+                        interval: item.interval,
+                        open: item.open,
+                        close: item.close,
+                        high: item.high,
+                        low: item.low,
+                        volume: item.volume,
+                        time: item.time.timestamp_millis(),
+                        sent: item.sent.timestamp_millis(),
+                        received: item.received.timestamp_millis(),
+                        dropped: 0,
                     }
                 }
             }
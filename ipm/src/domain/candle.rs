@@ -0,0 +1,15 @@
+use chrono::{DateTime, Utc};
+
+#[derive(Clone, Debug)]
+pub struct Candle {
+    pub figi: String,
+    pub interval: String,
+    pub open: f32,
+    pub close: f32,
+    pub high: f32,
+    pub low: f32,
+    pub volume: u64,
+    pub time: DateTime<Utc>,
+    pub sent: DateTime<Utc>,
+    pub received: DateTime<Utc>,
+}
@@ -1,2 +1,3 @@
+pub mod candle;
 pub mod order_book;
 pub mod trade;
@@ -5,6 +5,7 @@ pub struct Trade {
     pub price: f32,
     pub volume: u64,
     pub figi: String,
+    pub interval: String,
     pub minute_rounded: DateTime<Utc>,
     pub sent: DateTime<Utc>,
     pub received: DateTime<Utc>,
@@ -15,6 +16,7 @@ impl Trade {
         price: f32,
         volume: u64,
         figi: String,
+        interval: String,
         minute_rounded: DateTime<Utc>,
         sent: DateTime<Utc>,
         received: DateTime<Utc>,
@@ -23,6 +25,7 @@ impl Trade {
             price,
             volume,
             figi,
+            interval,
             minute_rounded,
             sent,
             received,
@@ -0,0 +1,9 @@
+//! Все, кроме разбора аргументов командной строки, вынесено в библиотеку, чтобы было доступно интеграционным тестам
+
+#[macro_use]
+mod convert;
+pub mod client;
+pub mod domain;
+pub mod server;
+pub mod settings;
+pub mod subscriptions;
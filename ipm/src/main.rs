@@ -1,23 +1,23 @@
 use flexi_logger::Logger;
-use log::{error, info};
-use tokio::sync::broadcast;
+use log::info;
 use tokio::time;
 use tokio_util::sync::CancellationToken;
 
 use args::Args;
-use client::ws::{self, emulator as ws_emulator};
-use domain::{order_book::OrderBook, trade::Trade};
-use receiver::ReceiverMaker;
-use settings::{Settings, Tinkoff};
+use ipm::client::outbox::{self, Outbox};
+use ipm::client::reconnect::{ConnectionMonitor, Reconnector};
+use ipm::client::{
+    self,
+    ws::{self, emulator as ws_emulator, replay as ws_replay},
+};
+use ipm::domain::{candle::Candle, order_book::OrderBook, trade::Trade};
+use ipm::server::{self, ReceiverMakers};
+use ipm::settings::{Repository, Settings, Tinkoff};
+use ipm::subscriptions::Subscriptions;
+use shared::channel::{self, BroadcastSender};
+use shared::receiver::ReceiverMaker;
 
-#[macro_use]
-mod convert;
 mod args;
-mod client;
-mod domain;
-mod receiver;
-mod server;
-mod settings;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -31,35 +31,85 @@ async fn main() -> anyhow::Result<()> {
     info!("incoming price manager started");
 
     let shutdown = run_ctrlc()?;
-    let (trade_sender, _) = broadcast::channel::<Trade>(20);
-    let (order_book_sender, _) = broadcast::channel::<OrderBook>(20);
-
-    match args.is_ws_emulate() {
-        true => {
-            ws_emulator::run(
-                &cfg.client.tinkoff.figis,
-                trade_sender.clone(),
-                order_book_sender.clone(),
-            )
-            .await?;
-        }
-        false => {
-            start_and_restart_ws_client(
-                cfg.client.tinkoff,
-                trade_sender.clone(),
-                order_book_sender.clone(),
-                shutdown.clone(),
-            )
-            .await;
-        }
+    let trade_sender = channel::broadcast::<Trade>("trade", cfg.channels.trade);
+    let order_book_sender = channel::broadcast::<OrderBook>("order_book", cfg.channels.order_book);
+    let candle_sender = channel::broadcast::<Candle>("candle", cfg.channels.candle);
+    let subscriptions = Subscriptions::new(&cfg.client.tinkoff.figis);
+    let ws_monitor = ConnectionMonitor::new("ws");
+    let pr_monitor = ConnectionMonitor::new("pr");
+
+    // Очередь на диске подписывается на данные до запуска источника, чтобы в pr попало все с самого начала
+    let outbox = if args.is_repository() {
+        let outbox = Outbox::open(&cfg.client.pr.outbox)?;
+        outbox::spool(
+            outbox.clone(),
+            trade_sender.subscribe(),
+            order_book_sender.subscribe(),
+            candle_sender.subscribe(),
+            shutdown.clone(),
+        );
+        Some(outbox)
+    } else {
+        None
+    };
+
+    if args.is_ws_emulate() {
+        let instruments: Vec<_> = subscriptions
+            .figis()
+            .iter()
+            .map(|f| cfg.client.tinkoff.instrument(f))
+            .collect();
+
+        let scenario = match args.get_scenario_path() {
+            Some(path) => Some(ws_emulator::Scenario::load(path)?),
+            None => None,
+        };
+
+        ws_emulator::run(
+            &cfg.client.emulator,
+            &instruments,
+            scenario.as_ref(),
+            trade_sender.clone(),
+            order_book_sender.clone(),
+            candle_sender.clone(),
+        )
+        .await?;
+    } else if let Some(path) = args.get_replay_path() {
+        let speed: ws_replay::Speed = args.get_replay_speed().unwrap_or("1").parse()?;
+        ws_replay::run(
+            path,
+            speed,
+            trade_sender.clone(),
+            order_book_sender.clone(),
+            candle_sender.clone(),
+            shutdown.clone(),
+        )
+        .await?;
+    } else {
+        start_and_restart_ws_client(
+            cfg.client.tinkoff,
+            subscriptions.clone(),
+            ws_monitor.clone(),
+            trade_sender.clone(),
+            order_book_sender.clone(),
+            candle_sender.clone(),
+            shutdown.clone(),
+        )
+        .await;
     }
 
-    let (trade_rm, order_book_rm) = create_receivers(trade_sender.clone(), order_book_sender.clone());
-    server::run(cfg.server.addr, trade_rm, order_book_rm, shutdown.clone()).await?;
-
-    if args.is_repository() {
-        let (trade_rm, order_book_rm) = create_receivers(trade_sender.clone(), order_book_sender.clone());
-        start_and_restart_grpc_client(cfg.client.pr.addr, trade_rm, order_book_rm, shutdown.clone()).await;
+    server::run(
+        cfg.server,
+        create_receivers(trade_sender, order_book_sender, candle_sender),
+        subscriptions.clone(),
+        vec![ws_monitor, pr_monitor.clone()],
+        outbox.clone(),
+        shutdown.clone(),
+    )
+    .await?;
+
+    if let Some(outbox) = outbox {
+        start_and_restart_grpc_client(cfg.client.pr, pr_monitor, outbox, shutdown.clone()).await;
     }
 
     // До этого были неблокирующие вызовы, поэтому ждем сигнала о завершении и блокируем поток
@@ -74,50 +124,80 @@ async fn main() -> anyhow::Result<()> {
 
 /// Небольшая вспомогательная функция - хелпер, сделал только для того, чтобы не загромождать основной код
 fn create_receivers(
-    trade_sender: broadcast::Sender<Trade>,
-    order_book_sender: broadcast::Sender<OrderBook>,
-) -> (ReceiverMaker<Trade>, ReceiverMaker<OrderBook>) {
-    (ReceiverMaker::new(trade_sender), ReceiverMaker::new(order_book_sender))
+    trade_sender: BroadcastSender<Trade>,
+    order_book_sender: BroadcastSender<OrderBook>,
+    candle_sender: BroadcastSender<Candle>,
+) -> ReceiverMakers {
+    ReceiverMakers {
+        trade: ReceiverMaker::new(trade_sender),
+        order_book: ReceiverMaker::new(order_book_sender),
+        candle: ReceiverMaker::new(candle_sender),
+    }
 }
 
-/// Запуск ws клиента и его и перезапуск в случае потери соединения
+/// Запуск ws клиента и его перезапуск в случае потери соединения (подробнее про задержки в client::reconnect)
 async fn start_and_restart_ws_client(
     tinkoff: Tinkoff,
-    trade_sender: broadcast::Sender<Trade>,
-    order_book_sender: broadcast::Sender<OrderBook>,
+    subscriptions: Subscriptions,
+    monitor: ConnectionMonitor,
+    trade_sender: BroadcastSender<Trade>,
+    order_book_sender: BroadcastSender<OrderBook>,
+    candle_sender: BroadcastSender<Candle>,
     shutdown: CancellationToken,
 ) {
+    let mut reconnector = Reconnector::new(tinkoff.reconnect.clone(), monitor);
     tokio::spawn(async move {
         loop {
-            tokio::select! {
-                Err(err) = ws::run(tinkoff.clone(), trade_sender.clone(), order_book_sender.clone(), shutdown.clone()) => {
-                    error!("ws client not running: {}, retry will be in 1 second", err);
-                    time::sleep(time::Duration::from_secs(1)).await;
-                },
+            reconnector.connecting();
+            let result = tokio::select! {
+                res = ws::run(tinkoff.clone(), subscriptions.clone(), reconnector.monitor(), trade_sender.clone(), order_book_sender.clone(), candle_sender.clone(), shutdown.clone()) => res,
                 _ = shutdown.cancelled() => {
                     return;
                 }
+            };
+
+            // Клиент завершается без ошибки только по сигналу на завершение
+            match result {
+                Err(err) if err.is_fatal() => {
+                    reconnector.fail(&err);
+                    return;
+                }
+                Err(err) => {
+                    if !reconnector.wait(&err, &shutdown).await {
+                        return;
+                    }
+                }
+                Ok(()) => return,
             }
         }
     });
 }
 
+/// Запуск grpc клиента и его перезапуск в случае потери соединения, пока pr недоступен, данные копятся в outbox
 async fn start_and_restart_grpc_client(
-    addr: String,
-    trade_rm: ReceiverMaker<Trade>,
-    order_book_rm: ReceiverMaker<OrderBook>,
+    pr: Repository,
+    monitor: ConnectionMonitor,
+    outbox: Outbox,
     shutdown: CancellationToken,
 ) {
+    let mut reconnector = Reconnector::new(pr.reconnect.clone(), monitor);
     tokio::spawn(async move {
         loop {
-            tokio::select! {
-                Err(err) = client::grpc::run(addr.clone(), trade_rm.receiver(), order_book_rm.receiver(), shutdown.clone()) => {
-                    error!("grpc client not running: {}, retry will be in 1 second", err);
-                    time::sleep(time::Duration::from_secs(1)).await;
-                },
+            reconnector.connecting();
+            let result = tokio::select! {
+                res = client::grpc::run(pr.addr.clone(), pr.source.clone(), reconnector.monitor(), outbox.clone(), shutdown.clone()) => res,
                 _ = shutdown.cancelled() => {
                     return;
                 }
+            };
+
+            // Клиент завершается без ошибки только по сигналу на завершение
+            if let Err(err) = result {
+                if !reconnector.wait(&err, &shutdown).await {
+                    return;
+                }
+            } else {
+                return;
             }
         }
     });
@@ -129,7 +209,7 @@ fn run_ctrlc() -> anyhow::Result<CancellationToken> {
     let token = CancellationToken::new();
     let t = token.clone();
     ctrlc::set_handler(move || {
-        let _ = t.cancel();
+        t.cancel();
     })?;
     Ok(token)
 }
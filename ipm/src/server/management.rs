@@ -0,0 +1,126 @@
+use log::info;
+use tonic::{Request, Response, Status};
+
+use control::control_server::Control;
+use control::{Connection, Connections, Empty, Instruments, Outbox as OutboxStats};
+
+use crate::client::outbox::Outbox;
+use crate::client::reconnect::{ConnectionMonitor, ConnectionState};
+use crate::subscriptions::Subscriptions;
+
+pub mod control {
+    tonic::include_proto!("control");
+}
+
+pub struct ControlService {
+    subscriptions: Subscriptions,
+    connections: Vec<ConnectionMonitor>,
+    outbox: Option<Outbox>,
+}
+
+impl ControlService {
+    pub fn new(subscriptions: Subscriptions, connections: Vec<ConnectionMonitor>, outbox: Option<Outbox>) -> Self {
+        ControlService {
+            subscriptions,
+            connections,
+            outbox,
+        }
+    }
+
+    fn instruments(&self) -> Response<Instruments> {
+        Response::new(Instruments {
+            figis: self.subscriptions.figis(),
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl Control for ControlService {
+    async fn subscribe(&self, request: Request<Instruments>) -> Result<Response<Instruments>, Status> {
+        let figis = extract_figis(request).ok_or_else(invalid_figis)?;
+        info!("subscribe request: {:?}", figis);
+
+        self.subscriptions.subscribe(figis);
+        Ok(self.instruments())
+    }
+
+    async fn unsubscribe(&self, request: Request<Instruments>) -> Result<Response<Instruments>, Status> {
+        let figis = extract_figis(request).ok_or_else(invalid_figis)?;
+        info!("unsubscribe request: {:?}", figis);
+
+        self.subscriptions.unsubscribe(figis);
+        Ok(self.instruments())
+    }
+
+    async fn get_instruments(&self, _request: Request<Empty>) -> Result<Response<Instruments>, Status> {
+        Ok(self.instruments())
+    }
+
+    async fn get_connections(&self, _request: Request<Empty>) -> Result<Response<Connections>, Status> {
+        let connections = self.connections.iter().map(convert_connection).collect();
+        Ok(Response::new(Connections { connections }))
+    }
+
+    async fn get_outbox(&self, _request: Request<Empty>) -> Result<Response<OutboxStats>, Status> {
+        let stats = match &self.outbox {
+            Some(outbox) => outbox.stats().await,
+            None => return Ok(Response::new(OutboxStats::default())),
+        };
+
+        Ok(Response::new(OutboxStats {
+            enabled: true,
+            pending: stats.pending,
+            size_bytes: stats.size_bytes,
+            max_size_bytes: stats.max_size_bytes,
+            written: stats.written,
+            sent: stats.sent,
+            dropped: stats.dropped,
+            rejected: stats.rejected,
+        }))
+    }
+}
+
+fn convert_connection(monitor: &ConnectionMonitor) -> Connection {
+    let (state, changed) = monitor.state();
+    let mut connection = Connection {
+        name: monitor.name().to_string(),
+        state: state.to_string(),
+        changed: changed.timestamp_millis(),
+        ..Default::default()
+    };
+
+    match state {
+        ConnectionState::Idle | ConnectionState::Connected => {}
+        ConnectionState::Connecting { attempt } => connection.attempt = attempt,
+        ConnectionState::Waiting {
+            attempt,
+            next_attempt,
+            error,
+        }
+        | ConnectionState::CircuitOpen {
+            attempt,
+            next_attempt,
+            error,
+        } => {
+            connection.attempt = attempt;
+            connection.error = error;
+            connection.next_attempt = next_attempt.timestamp_millis();
+        }
+        ConnectionState::Failed { error } => connection.error = error,
+    }
+
+    connection
+}
+
+fn extract_figis(request: Request<Instruments>) -> Option<Vec<String>> {
+    let figis = request.into_inner().figis;
+    if figis.is_empty() || figis.iter().any(|f| f.trim().is_empty()) {
+        return None;
+    }
+
+    Some(figis)
+}
+
+fn invalid_figis() -> Status {
+    Status::invalid_argument("figis must be specified and must not be empty")
+}
@@ -0,0 +1,63 @@
+use log::{error, info};
+use shared::receiver::ReceiverMaker;
+use shared::statistics::{stats::stats_server::StatsServer, StatsService};
+use tokio_util::sync::CancellationToken;
+use tonic::transport::Server;
+
+use crate::client::outbox::Outbox;
+use crate::client::reconnect::ConnectionMonitor;
+use crate::domain::{candle::Candle, order_book::OrderBook, trade::Trade};
+use crate::settings::Grpc;
+use crate::subscriptions::Subscriptions;
+
+use management::{control::control_server::ControlServer, ControlService};
+use price_stream::{incoming::price_stream_server::PriceStreamServer, PriceStreamService};
+
+mod management;
+mod price_stream;
+
+/// Генерация ресиверов для получения данных, транслируемых наружу сервиса
+pub struct ReceiverMakers {
+    pub trade: ReceiverMaker<Trade>,
+    pub order_book: ReceiverMaker<OrderBook>,
+    pub candle: ReceiverMaker<Candle>,
+}
+
+/// Запускает три сервиса в рамках одного grpc сервера.
+/// Один транслирует потоки trade, order book и candle потребителям, другой позволяет управлять сервисом во время работы
+/// (например, менять список инструментов, на которые подписан ws клиент, смотреть состояние подключений),
+/// третий отдает статистику по каналам (Stats).
+/// outbox - очередь отправки в pr, есть только в режиме подключения к репозиторию, ее состояние отдает Control.
+pub async fn run(
+    cfg: Grpc,
+    rms: ReceiverMakers,
+    subscriptions: Subscriptions,
+    connections: Vec<ConnectionMonitor>,
+    outbox: Option<Outbox>,
+    shutdown: CancellationToken,
+) -> anyhow::Result<()> {
+    let addr = cfg.addr.parse()?;
+    info!("price stream server listening on: {}", addr);
+
+    let stats_service = StatsService::new(vec![rms.trade.stats(), rms.order_book.stats(), rms.candle.stats()]);
+    let stream_service = PriceStreamService::new(rms.trade, rms.order_book, rms.candle, cfg.lag_policy);
+    let control_service = ControlService::new(subscriptions, connections, outbox);
+
+    tokio::spawn(async move {
+        let res = Server::builder()
+            .add_service(PriceStreamServer::new(stream_service))
+            .add_service(ControlServer::new(control_service))
+            .add_service(StatsServer::new(stats_service))
+            .serve_with_shutdown(addr, async {
+                shutdown.cancelled().await;
+                info!("grpc server finished");
+            })
+            .await;
+
+        if res.is_err() {
+            error!("could not start the grpc server");
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use std::convert::From;
+use std::pin::Pin;
+
+use futures::Stream;
+use log::info;
+use tonic::{Request, Response, Status};
+
+use incoming::price_stream_server::PriceStream;
+use incoming::{Candle, OrderBook, Subscription, Trade};
+
+use shared::filter::FigiFilter;
+use shared::receiver::ReceiverMaker;
+use shared::streaming::{stream, GapMarker, LagPolicy};
+
+pub mod incoming {
+    tonic::include_proto!("incoming");
+}
+
+trade_from!(incoming);
+order_book_from!(incoming);
+candle_from!(incoming);
+
+pub struct PriceStreamService {
+    trade_rm: ReceiverMaker<DomainTrade>,
+    order_book_rm: ReceiverMaker<DomainOrderBook>,
+    candle_rm: ReceiverMaker<DomainCandle>,
+    lag_policy: LagPolicy,
+}
+
+impl PriceStreamService {
+    pub fn new(
+        trade_rm: ReceiverMaker<DomainTrade>,
+        order_book_rm: ReceiverMaker<DomainOrderBook>,
+        candle_rm: ReceiverMaker<DomainCandle>,
+        lag_policy: LagPolicy,
+    ) -> Self {
+        PriceStreamService {
+            trade_rm,
+            order_book_rm,
+            candle_rm,
+            lag_policy,
+        }
+    }
+}
+
+impl GapMarker for Trade {
+    fn gap(dropped: u64) -> Self {
+        Trade {
+            dropped,
+            ..Default::default()
+        }
+    }
+}
+
+impl GapMarker for OrderBook {
+    fn gap(dropped: u64) -> Self {
+        OrderBook {
+            dropped,
+            ..Default::default()
+        }
+    }
+}
+
+impl GapMarker for Candle {
+    fn gap(dropped: u64) -> Self {
+        Candle {
+            dropped,
+            ..Default::default()
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl PriceStream for PriceStreamService {
+    type SubscribeToTradeStream = Pin<Box<dyn Stream<Item = Result<Trade, Status>> + Send + Sync + 'static>>;
+
+    async fn subscribe_to_trade(
+        &self,
+        request: Request<Subscription>,
+    ) -> Result<Response<Self::SubscribeToTradeStream>, Status> {
+        let filter = FigiFilter::new(request.into_inner().figis);
+        info!("new trade subscription: {:?}", filter);
+
+        let receiver = self.trade_rm.receiver();
+        let output = stream("trade", receiver, filter, |t| &t.figi, self.lag_policy);
+
+        Ok(Response::new(Box::pin(output) as Self::SubscribeToTradeStream))
+    }
+
+    type SubscribeToOrderBookStream = Pin<Box<dyn Stream<Item = Result<OrderBook, Status>> + Send + Sync + 'static>>;
+
+    async fn subscribe_to_order_book(
+        &self,
+        request: Request<Subscription>,
+    ) -> Result<Response<Self::SubscribeToOrderBookStream>, Status> {
+        let filter = FigiFilter::new(request.into_inner().figis);
+        info!("new order book subscription: {:?}", filter);
+
+        let receiver = self.order_book_rm.receiver();
+        let output = stream("order book", receiver, filter, |ob| &ob.figi, self.lag_policy);
+
+        Ok(Response::new(Box::pin(output) as Self::SubscribeToOrderBookStream))
+    }
+
+    type SubscribeToCandleStream = Pin<Box<dyn Stream<Item = Result<Candle, Status>> + Send + Sync + 'static>>;
+
+    async fn subscribe_to_candle(
+        &self,
+        request: Request<Subscription>,
+    ) -> Result<Response<Self::SubscribeToCandleStream>, Status> {
+        let filter = FigiFilter::new(request.into_inner().figis);
+        info!("new candle subscription: {:?}", filter);
+
+        let receiver = self.candle_rm.receiver();
+        let output = stream("candle", receiver, filter, |c| &c.figi, self.lag_policy);
+
+        Ok(Response::new(Box::pin(output) as Self::SubscribeToCandleStream))
+    }
+}
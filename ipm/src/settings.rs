@@ -1,9 +1,17 @@
+use chrono::NaiveTime;
 use config::{Config, ConfigError, File};
 use serde::Deserialize;
+use shared::streaming::LagPolicy;
 
 const CONFIG_DEFAULT_FILE: &str = "default.yaml";
 const CONFIGS_DEFAULT_PATH: &str = "./configs/";
 
+// https://tinkoffcreditsystems.github.io/invest-openapi/marketdata/
+const CANDLE_INTERVALS: [&str; 13] = [
+    "1min", "2min", "3min", "5min", "10min", "15min", "30min", "hour", "2hour", "4hour", "day", "week", "month",
+];
+const ORDER_BOOK_MAX_DEPTH: u32 = 20;
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Log {
     pub level: String,
@@ -14,17 +22,303 @@ pub struct Tinkoff {
     pub ws: String,
     pub figis: Vec<String>,
     pub token: String,
+    pub candle_intervals: Vec<String>,
+    pub order_book_depth: u32,
+    #[serde(default)]
+    pub instruments: Vec<InstrumentOverride>,
+    pub reconnect: Reconnect,
+    pub heartbeat: Heartbeat,
+    pub recorder: Recorder,
+}
+
+/// Запись сырых ws фреймов в файлы, подробнее в client::ws::recorder
+#[derive(Debug, Deserialize, Clone)]
+pub struct Recorder {
+    pub enabled: bool,
+    pub dir: String,
+    pub rotate_secs: u64,
+    pub max_file_size_mb: u64,
+}
+
+/// Настройки проверки "живости" ws соединения, подробнее в client::ws::watchdog
+#[derive(Debug, Deserialize, Clone)]
+pub struct Heartbeat {
+    pub ping_interval_secs: u64,
+    pub timeout_secs: u64,
+    pub figi_stale_secs: u64,
+    pub trading_hours: TradingHours,
+}
+
+/// Время в UTC, формат HH:MM:SS
+#[derive(Debug, Deserialize, Clone)]
+pub struct TradingHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+/// Настройки переподключения, подробнее в client::reconnect
+#[derive(Debug, Deserialize, Clone)]
+pub struct Reconnect {
+    pub initial_delay_ms: u64,
+    pub max_delay_ms: u64,
+    pub multiplier: f64,
+    pub jitter: f64,
+    pub max_attempts: u32,
+    pub circuit_open_secs: u64,
+}
+
+impl Reconnect {
+    fn validate(&self, name: &str) -> Result<(), ConfigError> {
+        if self.initial_delay_ms == 0 || self.max_delay_ms < self.initial_delay_ms {
+            return Err(ConfigError::Message(format!(
+                "{} reconnect initial delay must be greater than 0 and not greater than max delay",
+                name
+            )));
+        }
+
+        if self.multiplier < 1.0 {
+            return Err(ConfigError::Message(format!(
+                "{} reconnect multiplier must be at least 1.0",
+                name
+            )));
+        }
+
+        if !(0.0..=1.0).contains(&self.jitter) {
+            return Err(ConfigError::Message(format!(
+                "{} reconnect jitter must be in range 0..=1",
+                name
+            )));
+        }
+
+        if self.max_attempts > 0 && self.circuit_open_secs == 0 {
+            return Err(ConfigError::Message(format!(
+                "{} reconnect circuit open time must be greater than 0",
+                name
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Индивидуальные настройки инструмента, если не заданы - используются общие настройки из Tinkoff
+#[derive(Debug, Deserialize, Clone)]
+pub struct InstrumentOverride {
+    pub figi: String,
+    pub candle_intervals: Option<Vec<String>>,
+    pub order_book_depth: Option<u32>,
+}
+
+/// Итоговые настройки подписки на инструмент
+#[derive(Debug, Clone)]
+pub struct Instrument {
+    pub figi: String,
+    pub candle_intervals: Vec<String>,
+    pub order_book_depth: u32,
+}
+
+impl Tinkoff {
+    pub fn instrument(&self, figi: &str) -> Instrument {
+        let o = self.instruments.iter().find(|i| i.figi == figi);
+        Instrument {
+            figi: figi.to_string(),
+            candle_intervals: o
+                .and_then(|i| i.candle_intervals.clone())
+                .unwrap_or_else(|| self.candle_intervals.clone()),
+            order_book_depth: o.and_then(|i| i.order_book_depth).unwrap_or(self.order_book_depth),
+        }
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        self.reconnect.validate("tinkoff")?;
+
+        if self.heartbeat.ping_interval_secs == 0 {
+            return Err(ConfigError::Message(
+                "heartbeat ping interval must be greater than 0".into(),
+            ));
+        }
+
+        if self.heartbeat.timeout_secs <= self.heartbeat.ping_interval_secs {
+            return Err(ConfigError::Message(
+                "heartbeat timeout must be greater than ping interval".into(),
+            ));
+        }
+
+        let figis = self.figis.iter().chain(self.instruments.iter().map(|i| &i.figi));
+        for instrument in figis.map(|f| self.instrument(f)) {
+            if instrument.candle_intervals.is_empty() {
+                return Err(ConfigError::Message(format!(
+                    "candle intervals are not specified for {}",
+                    instrument.figi
+                )));
+            }
+
+            if let Some(interval) = instrument
+                .candle_intervals
+                .iter()
+                .find(|i| !CANDLE_INTERVALS.contains(&i.as_str()))
+            {
+                return Err(ConfigError::Message(format!(
+                    "unknown candle interval {} for {}",
+                    interval, instrument.figi
+                )));
+            }
+
+            if instrument.order_book_depth == 0 || instrument.order_book_depth > ORDER_BOOK_MAX_DEPTH {
+                return Err(ConfigError::Message(format!(
+                    "order book depth for {} must be in range 1..={}",
+                    instrument.figi, ORDER_BOOK_MAX_DEPTH
+                )));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Grpc {
     pub addr: String,
+    pub lag_policy: LagPolicy,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Repository {
+    pub addr: String,
+    /// Имя источника данных для pr, по нему (вместе с номером сообщения) pr отбрасывает дубликаты
+    pub source: String,
+    pub reconnect: Reconnect,
+    pub outbox: Outbox,
+}
+
+/// Очередь отправки в pr на диске, подробнее в client::outbox
+#[derive(Debug, Deserialize, Clone)]
+pub struct Outbox {
+    pub dir: String,
+    /// При достижении этого размера новые данные отбрасываются
+    pub max_size_mb: u64,
+    /// Размер файла (сегмента) очереди, полностью отправленные сегменты удаляются
+    pub segment_size_mb: u64,
+}
+
+impl Repository {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.source.trim().is_empty() {
+            return Err(ConfigError::Message("pr source must be specified".into()));
+        }
+
+        self.reconnect.validate("pr")?;
+        self.outbox.validate()
+    }
+}
+
+impl Outbox {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.segment_size_mb == 0 || self.max_size_mb < self.segment_size_mb {
+            return Err(ConfigError::Message(
+                "outbox segment size must be greater than 0 and not greater than max size".into(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Настройки эмулятора рынка (режим ws эмуляции), подробнее в client::ws::emulator
+#[derive(Debug, Deserialize, Clone)]
+pub struct Emulator {
+    /// Если задан, то последовательность сделок воспроизводима, иначе выбирается случайно (и пишется в лог)
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Шаг цены
+    pub tick: f64,
+    /// Спред между лучшими bid и ask в шагах цены
+    pub spread_ticks: u32,
+    /// Максимальный объем сделки и заявки на уровне книги заказов в лотах
+    pub max_lot: u64,
+    /// Средний интервал между сделками и между обновлениями книги заказов, фактический - случайный от 0.5 до 1.5 среднего
+    pub trade_interval_ms: u64,
+    pub order_book_interval_ms: u64,
+    /// Процесс изменения цены, общий для всех инструментов
+    pub process: PriceProcess,
+    /// Индивидуальные процессы по инструментам
+    #[serde(default)]
+    pub instruments: Vec<EmulatedInstrument>,
+}
+
+/// Модель изменения цены, параметры заданы на один шаг (одну сделку), z - случайная величина N(0, 1)
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum PriceProcess {
+    /// price += step * z
+    RandomWalk { price: f64, step: f64 },
+    /// Геометрическое броуновское движение: price *= exp(drift - volatility^2 / 2 + volatility * z)
+    Gbm { price: f64, drift: f64, volatility: f64 },
+    /// Возврат к среднему (Орнштейн-Уленбек): price += speed * (mean - price) + volatility * z
+    MeanReversion {
+        price: f64,
+        mean: f64,
+        speed: f64,
+        volatility: f64,
+    },
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct EmulatedInstrument {
+    pub figi: String,
+    pub process: PriceProcess,
+}
+
+impl Emulator {
+    pub fn process(&self, figi: &str) -> PriceProcess {
+        self.instruments
+            .iter()
+            .find(|i| i.figi == figi)
+            .map(|i| i.process.clone())
+            .unwrap_or_else(|| self.process.clone())
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.tick <= 0.0 || self.spread_ticks == 0 || self.max_lot == 0 {
+            return Err(ConfigError::Message(
+                "emulator tick, spread and max lot must be greater than 0".into(),
+            ));
+        }
+
+        if self.trade_interval_ms == 0 || self.order_book_interval_ms == 0 {
+            return Err(ConfigError::Message("emulator intervals must be greater than 0".into()));
+        }
+
+        let processes = std::iter::once(&self.process).chain(self.instruments.iter().map(|i| &i.process));
+        for process in processes {
+            let valid = match *process {
+                PriceProcess::RandomWalk { price, step } => price > 0.0 && step >= 0.0,
+                PriceProcess::Gbm { price, volatility, .. } => price > 0.0 && volatility >= 0.0,
+                PriceProcess::MeanReversion {
+                    price,
+                    mean,
+                    speed,
+                    volatility,
+                } => price > 0.0 && mean > 0.0 && (0.0..=1.0).contains(&speed) && volatility >= 0.0,
+            };
+
+            if !valid {
+                return Err(ConfigError::Message(format!(
+                    "invalid emulator price process: {:?}",
+                    process
+                )));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Client {
     pub tinkoff: Tinkoff,
-    pub pr: Grpc,
+    pub pr: Repository,
+    pub emulator: Emulator,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -34,12 +328,33 @@ pub enum Env {
     Production,
 }
 
+/// Емкость broadcast каналов, через которые данные от ws клиента (эмулятора) транслируются потребителям
+#[derive(Debug, Deserialize, Clone)]
+pub struct Channels {
+    pub trade: usize,
+    pub order_book: usize,
+    pub candle: usize,
+}
+
+impl Channels {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.trade == 0 || self.order_book == 0 || self.candle == 0 {
+            return Err(ConfigError::Message(
+                "channel capacity must be greater than 0".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Settings {
     pub log: Log,
     pub env: Env,
     pub server: Grpc,
     pub client: Client,
+    pub channels: Channels,
 }
 
 // Пример отсюда: https://blog.logrocket.com/configuration-management-in-rust-web-services/
@@ -59,6 +374,12 @@ impl Settings {
         cfg.merge(File::with_name(&format!("{}{}", path, CONFIG_DEFAULT_FILE)))?;
         cfg.merge(File::with_name(&format!("{}{}", path, file_name)))?;
 
-        cfg.try_into()
+        let settings: Settings = cfg.try_into()?;
+        settings.client.tinkoff.validate()?;
+        settings.client.emulator.validate()?;
+        settings.client.pr.validate()?;
+        settings.channels.validate()?;
+
+        Ok(settings)
     }
 }
@@ -0,0 +1,64 @@
+use std::collections::BTreeSet;
+use std::sync::{Arc, RwLock};
+
+use tokio::sync::broadcast;
+
+const CHANGES_CAPACITY: usize = 100;
+
+#[derive(Clone, Debug)]
+pub enum Change {
+    Subscribe(String),
+    Unsubscribe(String),
+}
+
+/// Актуальный набор инструментов, на которые подписан ws клиент.
+/// Изменения транслируются в живое ws соединение через канал changes,
+/// а после переподключения ws клиент заново подписывается на весь актуальный набор.
+#[derive(Clone)]
+pub struct Subscriptions {
+    figis: Arc<RwLock<BTreeSet<String>>>,
+    changes: broadcast::Sender<Change>,
+}
+
+impl Subscriptions {
+    pub fn new(figis: &[String]) -> Self {
+        let (changes, _) = broadcast::channel(CHANGES_CAPACITY);
+        Subscriptions {
+            figis: Arc::new(RwLock::new(figis.iter().cloned().collect())),
+            changes,
+        }
+    }
+
+    pub fn figis(&self) -> Vec<String> {
+        self.figis.read().unwrap().iter().cloned().collect()
+    }
+
+    /// Подписываться на изменения нужно до того, как будет прочитан актуальный набор (figis),
+    /// иначе можно пропустить изменения, которые произойдут между этими вызовами
+    pub fn changes(&self) -> broadcast::Receiver<Change> {
+        self.changes.subscribe()
+    }
+
+    pub fn subscribe(&self, figis: Vec<String>) {
+        let mut current = self.figis.write().unwrap();
+        for figi in figis {
+            if current.insert(figi.clone()) {
+                self.notify(Change::Subscribe(figi));
+            }
+        }
+    }
+
+    pub fn unsubscribe(&self, figis: Vec<String>) {
+        let mut current = self.figis.write().unwrap();
+        for figi in figis {
+            if current.remove(&figi) {
+                self.notify(Change::Unsubscribe(figi));
+            }
+        }
+    }
+
+    fn notify(&self, change: Change) {
+        // Если ws клиент сейчас не подключен, то ошибку игнорируем, при подключении он возьмет актуальный набор
+        let _ = self.changes.send(change);
+    }
+}
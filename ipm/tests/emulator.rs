@@ -0,0 +1,227 @@
+//! Тесты эмулятора рынка (client::ws::emulator::Market) и сценариев (client::ws::emulator::Scenario)
+
+use chrono::{Duration, TimeZone, Utc};
+
+use ipm::client::ws::emulator::scenario::{Action, Side};
+use ipm::client::ws::emulator::{Market, Scenario};
+use ipm::settings::{Emulator, Instrument, PriceProcess};
+
+fn config(process: PriceProcess) -> Emulator {
+    Emulator {
+        seed: None,
+        tick: 0.01,
+        spread_ticks: 2,
+        max_lot: 100,
+        trade_interval_ms: 1000,
+        order_book_interval_ms: 500,
+        process,
+        instruments: vec![],
+    }
+}
+
+fn instrument(figi: &str) -> Instrument {
+    Instrument {
+        figi: figi.to_string(),
+        candle_intervals: vec!["1min".to_string(), "hour".to_string()],
+        order_book_depth: 5,
+    }
+}
+
+fn gbm() -> PriceProcess {
+    PriceProcess::Gbm {
+        price: 100.0,
+        drift: 0.0,
+        volatility: 0.01,
+    }
+}
+
+/// Цены и объемы сделок (по первому интервалу) за n шагов, время сделок сдвигается на секунду
+fn trades(market: &mut Market, n: i64) -> Vec<(f32, u64)> {
+    let start = Utc.ymd(2021, 7, 1).and_hms(10, 0, 0);
+    (0..n)
+        .map(|i| {
+            let candles = market.trade(start + Duration::seconds(i));
+            (candles[0].close, candles[0].volume)
+        })
+        .collect()
+}
+
+#[test]
+fn same_seed_gives_same_trades() {
+    let cfg = config(gbm());
+    let figi = instrument("BBG000B9XRY4");
+
+    let mut first = Market::new(&cfg, &figi, 42);
+    let mut second = Market::new(&cfg, &figi, 42);
+    // Обновления книги заказов не должны влиять на последовательность сделок
+    second.order_book(Utc::now());
+    second.order_book_delay(500);
+    assert_eq!(trades(&mut first, 100), trades(&mut second, 100));
+
+    let mut other_seed = Market::new(&cfg, &figi, 43);
+    let mut other_figi = Market::new(&cfg, &instrument("BBG000N9MNX3"), 42);
+    let expected = trades(&mut Market::new(&cfg, &figi, 42), 100);
+    assert_ne!(expected, trades(&mut other_seed, 100));
+    assert_ne!(expected, trades(&mut other_figi, 100));
+}
+
+#[test]
+fn trades_do_not_change_order_books() {
+    let cfg = config(gbm());
+    let figi = instrument("BBG000B9XRY4");
+    let now = Utc.ymd(2021, 7, 1).and_hms(10, 0, 0);
+    let disorder = Action::Disorder {
+        count: 1000,
+        max_shift_ms: 5000,
+    };
+
+    // Сделки со сдвигом времени отправки (out_of_order) не должны трогать генератор книги заказов
+    let mut first = Market::new(&cfg, &figi, 42);
+    first.apply(&disorder);
+    trades(&mut first, 10);
+    let mut second = Market::new(&cfg, &figi, 42);
+    second.apply(&disorder);
+
+    // Цены уровней зависят от цены сделок, а объемы и сдвиг времени отправки - только от генератора книги
+    let lots = |levels: &[(f32, u64)]| levels.iter().map(|(_, lot)| *lot).collect::<Vec<_>>();
+    for _ in 0..10 {
+        let (a, b) = (first.order_book(now), second.order_book(now));
+        assert_eq!(
+            (lots(&a.bids), lots(&a.asks), a.sent),
+            (lots(&b.bids), lots(&b.asks), b.sent)
+        );
+    }
+}
+
+#[test]
+fn order_book_bids_below_asks() {
+    let processes = vec![
+        gbm(),
+        PriceProcess::RandomWalk { price: 1.0, step: 0.5 },
+        PriceProcess::MeanReversion {
+            price: 50.0,
+            mean: 50.0,
+            speed: 0.1,
+            volatility: 1.0,
+        },
+    ];
+
+    for process in processes {
+        let mut market = Market::new(&config(process), &instrument("BBG000B9XRY4"), 7);
+        for _ in 0..500 {
+            market.trade(Utc::now());
+            let book = market.order_book(Utc::now());
+
+            assert!(market.price() > 0.0);
+            assert_eq!(book.asks.len(), 5);
+            assert!(!book.bids.is_empty() && book.bids.len() <= 5);
+            assert!(
+                book.bids[0].0 < book.asks[0].0,
+                "bids: {:?}, asks: {:?}",
+                book.bids,
+                book.asks
+            );
+            assert!(book.bids.windows(2).all(|w| w[0].0 > w[1].0));
+            assert!(book.asks.windows(2).all(|w| w[0].0 < w[1].0));
+            assert!(book
+                .bids
+                .iter()
+                .chain(book.asks.iter())
+                .all(|(p, v)| *p > 0.0 && *v > 0));
+        }
+    }
+}
+
+#[test]
+fn candles_are_consistent_with_trades() {
+    let mut market = Market::new(&config(gbm()), &instrument("BBG000B9XRY4"), 1);
+    let start = Utc.ymd(2021, 7, 1).and_hms(10, 0, 0);
+
+    let mut volume = 0;
+    let mut last = Vec::new();
+    for i in 0..120 {
+        last = market.trade(start + Duration::seconds(i));
+        assert_eq!(last.len(), 2);
+        for candle in last.iter() {
+            assert!(candle.low <= candle.open.min(candle.close));
+            assert!(candle.high >= candle.open.max(candle.close));
+            assert_eq!(candle.close, market.price() as f32);
+        }
+
+        // Минутная свеча начинается заново каждую минуту, часовая накапливает объем всех сделок
+        if i == 60 {
+            assert_eq!(last[0].time, start + Duration::minutes(1));
+            assert_eq!(last[0].open, last[0].close);
+        }
+        volume = last[0].volume.max(volume);
+    }
+
+    assert_eq!(last[1].time, start);
+    assert!(last[1].volume >= volume);
+}
+
+#[test]
+fn example_scenario_is_valid() {
+    let scenario = Scenario::load("./scenarios/edge_cases.yaml").unwrap();
+    let timeline = scenario.timeline("BBG000B9XRY4");
+
+    // flash crash, empty book, freeze и burst раскладываются на начало и конец
+    assert_eq!(timeline.len(), 11);
+    assert!(timeline.windows(2).all(|w| w[0].0 <= w[1].0));
+    assert_eq!(timeline[2].1, Action::Shift(0.8));
+    assert!(scenario.timeline("unknown").is_empty());
+}
+
+#[test]
+fn scenario_burst_rate_is_limited() {
+    let path = std::env::temp_dir().join(format!("scenario-{}.yaml", std::process::id()));
+    let load = |rate: u64| {
+        let yaml = format!(
+            "instruments:\n  BBG000B9XRY4:\n    - {{ at_ms: 0, event: burst, rate: {}, duration_ms: 1000 }}\n",
+            rate
+        );
+        std::fs::write(&path, yaml).unwrap();
+        Scenario::load(path.to_str().unwrap())
+    };
+
+    assert!(load(100_000).is_ok());
+    assert!(load(0).is_err());
+    assert!(load(2_000_000).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn scenario_actions_change_market() {
+    let mut market = Market::new(&config(gbm()), &instrument("BBG000B9XRY4"), 3);
+    let now = Utc::now();
+    market.trade(now);
+
+    let price = market.price();
+    market.apply(&Action::Shift(1.1));
+    assert!((market.price() - price * 1.1).abs() < 0.011);
+
+    market.apply(&Action::EmptyBook(Some(Side::Bids)));
+    let book = market.order_book(now);
+    assert!(book.bids.is_empty() && !book.asks.is_empty());
+    market.apply(&Action::EmptyBook(None));
+    assert!(!market.order_book(now).bids.is_empty());
+
+    market.apply(&Action::Freeze(true));
+    assert!(market.is_frozen());
+    market.apply(&Action::Freeze(false));
+    assert!(!market.is_frozen());
+
+    market.apply(&Action::Rate(Some(1000)));
+    assert_eq!(market.trade_delay(1000), std::time::Duration::from_millis(1));
+    market.apply(&Action::Rate(None));
+    assert!(market.trade_delay(1000) >= std::time::Duration::from_millis(500));
+
+    market.apply(&Action::Disorder {
+        count: 10,
+        max_shift_ms: 5000,
+    });
+    let sent: Vec<_> = (0..10).map(|_| market.order_book(now).sent).collect();
+    assert!(sent.iter().all(|s| *s <= now));
+    assert!(sent.iter().any(|s| *s < now));
+    assert_eq!(market.order_book(now).sent, now);
+}
@@ -0,0 +1,340 @@
+//! Интеграционные тесты grpc клиента репозитория (client::grpc::run) против тестовой реализации PriceStorage,
+//! данные для отправки берутся из очереди на диске (client::outbox)
+
+use std::collections::HashSet;
+use std::fs;
+use std::net::{SocketAddr, TcpListener};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::Utc;
+use futures::Stream;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::sync::CancellationToken;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+
+use ipm::client::grpc;
+use ipm::client::outbox::{self, Outbox};
+use ipm::client::reconnect::ConnectionMonitor;
+use ipm::domain::{candle::Candle, order_book::OrderBook, trade::Trade};
+use ipm::settings::Outbox as OutboxCfg;
+use shared::channel::{self, BroadcastSender};
+
+use incoming::{Candle as ProtoCandle, OrderBook as ProtoOrderBook, Trade as ProtoTrade};
+use storage::price_storage_server::{PriceStorage, PriceStorageServer};
+use storage::{envelope, Ack, Code, Envelope, Resp};
+
+pub mod incoming {
+    tonic::include_proto!("incoming");
+}
+
+pub mod storage {
+    tonic::include_proto!("storage");
+}
+
+const FIGI: &str = "BBG000B9XRY4";
+const SOURCE: &str = "ipm";
+const WAIT: Duration = Duration::from_secs(5);
+
+/// Сохраняет полученные сделки, дубликаты (по source и sequence) отбрасывает, сделки с отрицательной ценой отклоняет
+/// (INVALID_PAYLOAD), как это делает pr. Используется только Deliver, остальные вызовы в ipm больше не используются.
+/// Если задан fail, то поток сразу завершается с ошибкой. Если задан break_after, то первый поток завершается
+/// на сообщении с этим номером (по порядку в потоке, с 1): сообщение сохраняется, но в ответ уходит STORAGE_ERROR.
+#[derive(Default, Clone)]
+struct Storage {
+    trades: Arc<Mutex<Vec<ProtoTrade>>>,
+    keys: Arc<Mutex<HashSet<(String, u64)>>>,
+    received: Arc<Mutex<Vec<u64>>>,
+    fail: bool,
+    break_after: Arc<Mutex<Option<usize>>>,
+}
+
+impl Storage {
+    fn store(&self, envelope: Envelope, count: usize) -> Code {
+        self.received.lock().unwrap().push(envelope.sequence);
+        if let Some(envelope::Item::Trade(trade)) = &envelope.item {
+            if trade.price < 0.0 {
+                return Code::InvalidPayload;
+            }
+        }
+
+        let key = (envelope.source, envelope.sequence);
+        if self.keys.lock().unwrap().insert(key) {
+            if let Some(envelope::Item::Trade(trade)) = envelope.item {
+                self.trades.lock().unwrap().push(trade);
+            }
+        }
+
+        let mut break_after = self.break_after.lock().unwrap();
+        if *break_after == Some(count) {
+            *break_after = None;
+            return Code::StorageError;
+        }
+        Code::Accepted
+    }
+}
+
+#[tonic::async_trait]
+impl PriceStorage for Storage {
+    async fn add_trade(&self, _request: Request<ProtoTrade>) -> Result<Response<Resp>, Status> {
+        Err(Status::unimplemented("unary calls are not expected"))
+    }
+
+    async fn add_order_book(&self, _request: Request<ProtoOrderBook>) -> Result<Response<Resp>, Status> {
+        Err(Status::unimplemented("unary calls are not expected"))
+    }
+
+    async fn add_candle(&self, _request: Request<ProtoCandle>) -> Result<Response<Resp>, Status> {
+        Err(Status::unimplemented("unary calls are not expected"))
+    }
+
+    async fn stream_trades(&self, _request: Request<Streaming<ProtoTrade>>) -> Result<Response<Resp>, Status> {
+        Err(Status::unimplemented("client streaming is not expected"))
+    }
+
+    async fn stream_order_books(&self, _request: Request<Streaming<ProtoOrderBook>>) -> Result<Response<Resp>, Status> {
+        Err(Status::unimplemented("client streaming is not expected"))
+    }
+
+    async fn stream_candles(&self, _request: Request<Streaming<ProtoCandle>>) -> Result<Response<Resp>, Status> {
+        Err(Status::unimplemented("client streaming is not expected"))
+    }
+
+    type DeliverStream = Pin<Box<dyn Stream<Item = Result<Ack, Status>> + Send + Sync + 'static>>;
+
+    async fn deliver(&self, request: Request<Streaming<Envelope>>) -> Result<Response<Self::DeliverStream>, Status> {
+        if self.fail {
+            return Err(Status::unavailable("storage is not available"));
+        }
+
+        let mut inbound = request.into_inner();
+        let storage = self.clone();
+        let (acks, output) = mpsc::channel(100);
+        tokio::spawn(async move {
+            let mut count = 0;
+            while let Ok(Some(envelope)) = inbound.message().await {
+                count += 1;
+                let sequence = envelope.sequence;
+                let status = storage.store(envelope, count);
+                let ack = Ack {
+                    sequence,
+                    status: status as i32,
+                    message: String::new(),
+                };
+
+                // После ошибки сохранения поток завершается, как это делает pr
+                if acks.send(Ok(ack)).await.is_err() || status == Code::StorageError {
+                    return;
+                }
+            }
+        });
+
+        Ok(Response::new(
+            Box::pin(ReceiverStream::new(output)) as Self::DeliverStream
+        ))
+    }
+}
+
+/// Запускает тестовый репозиторий, возвращает его адрес, сигнал на остановку и хендл задачи сервера
+fn start_storage(storage: Storage) -> (SocketAddr, CancellationToken, JoinHandle<()>) {
+    let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let shutdown = CancellationToken::new();
+    let s = shutdown.clone();
+    let handle = tokio::spawn(async move {
+        Server::builder()
+            .add_service(PriceStorageServer::new(storage))
+            .serve_with_shutdown(addr, async move { s.cancelled().await })
+            .await
+            .unwrap();
+    });
+
+    (addr, shutdown, handle)
+}
+
+/// Пустая очередь во временном каталоге (у каждого теста свой каталог)
+fn open_outbox(name: &str) -> Outbox {
+    let dir = std::env::temp_dir().join(format!("ipm-grpc-client-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    Outbox::open(&OutboxCfg {
+        dir: dir.to_string_lossy().to_string(),
+        max_size_mb: 1,
+        segment_size_mb: 1,
+    })
+    .unwrap()
+}
+
+fn run(addr: SocketAddr, outbox: &Outbox, shutdown: &CancellationToken) -> JoinHandle<anyhow::Result<()>> {
+    tokio::spawn(grpc::run(
+        addr.to_string(),
+        SOURCE.to_string(),
+        ConnectionMonitor::new("pr"),
+        outbox.clone(),
+        shutdown.clone(),
+    ))
+}
+
+async fn wait_for_trades(storage: &Storage, count: usize) {
+    timeout(WAIT, async {
+        while storage.trades.lock().unwrap().len() < count {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("trades are not delivered");
+}
+
+fn prices(storage: &Storage) -> Vec<f32> {
+    storage.trades.lock().unwrap().iter().map(|t| t.price).collect()
+}
+
+fn trade(price: f32) -> Trade {
+    let now = Utc::now();
+    Trade::new(price, 1, FIGI.to_string(), "1min".to_string(), now, now, now)
+}
+
+async fn push_trades(outbox: &Outbox, prices: std::ops::Range<u32>) {
+    for price in prices {
+        let trade = grpc::incoming::Trade::from(trade(price as f32));
+        outbox.push(outbox::Item::Trade(trade)).await;
+    }
+}
+
+#[tokio::test]
+async fn streams_trades_without_waiting_for_replies() {
+    let storage = Storage::default();
+    let (addr, server_shutdown, _) = start_storage(storage.clone());
+
+    let trade_sender: BroadcastSender<Trade> = channel::broadcast("trade", 100);
+    let order_book_sender: BroadcastSender<OrderBook> = channel::broadcast("order_book", 100);
+    let candle_sender: BroadcastSender<Candle> = channel::broadcast("candle", 100);
+    let outbox = open_outbox("streams");
+    let shutdown = CancellationToken::new();
+
+    outbox::spool(
+        outbox.clone(),
+        trade_sender.subscribe(),
+        order_book_sender.subscribe(),
+        candle_sender.subscribe(),
+        shutdown.clone(),
+    );
+    let client = run(addr, &outbox, &shutdown);
+
+    for price in 0..50 {
+        trade_sender.send(trade(price as f32)).unwrap();
+    }
+
+    wait_for_trades(&storage, 50).await;
+    assert_eq!(prices(&storage), (0..50).map(|p| p as f32).collect::<Vec<_>>());
+
+    // Сообщения пронумерованы по порядку в рамках источника (имя из настроек и идентификатор очереди)
+    let source = format!("{}/{}", SOURCE, outbox.id());
+    let keys = storage.keys.lock().unwrap().clone();
+    assert!((0..50).all(|sequence| keys.contains(&(source.clone(), sequence))));
+
+    // По сигналу на завершение поток закрывается, и клиент завершается без ошибки
+    shutdown.cancel();
+    assert!(timeout(WAIT, client).await.unwrap().unwrap().is_ok());
+    server_shutdown.cancel();
+}
+
+#[tokio::test]
+async fn returns_error_when_stream_fails() {
+    let storage = Storage {
+        fail: true,
+        ..Default::default()
+    };
+    let (addr, server_shutdown, _) = start_storage(storage);
+
+    // Ошибка потока завершает клиент с ошибкой, после чего он переподключается
+    let client = run(addr, &open_outbox("fails"), &CancellationToken::new());
+    assert!(timeout(WAIT, client).await.unwrap().unwrap().is_err());
+    server_shutdown.cancel();
+}
+
+#[tokio::test]
+async fn delivers_backlog_after_reconnect() {
+    let outbox = open_outbox("backlog");
+    push_trades(&outbox, 0..20).await;
+
+    // pr недоступен: данные остаются в очереди
+    let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let res = timeout(WAIT, run(addr, &outbox, &CancellationToken::new())).await;
+    assert!(res.unwrap().unwrap().is_err());
+    assert_eq!(outbox.stats().await.pending, 20);
+
+    // После подключения сначала отправляется накопленное, затем новые данные
+    let storage = Storage::default();
+    let (addr, server_shutdown, _) = start_storage(storage.clone());
+    let shutdown = CancellationToken::new();
+    let client = run(addr, &outbox, &shutdown);
+
+    push_trades(&outbox, 20..21).await;
+    wait_for_trades(&storage, 21).await;
+    assert_eq!(prices(&storage), (0..21).map(|p| p as f32).collect::<Vec<_>>());
+
+    shutdown.cancel();
+    assert!(timeout(WAIT, client).await.unwrap().unwrap().is_ok());
+    assert_eq!((outbox.stats().await.pending, outbox.stats().await.sent), (0, 21));
+    server_shutdown.cancel();
+}
+
+#[tokio::test]
+async fn resends_unacknowledged_without_duplicates() {
+    let storage = Storage {
+        break_after: Arc::new(Mutex::new(Some(5))),
+        ..Default::default()
+    };
+    let (addr, server_shutdown, _) = start_storage(storage.clone());
+    let outbox = open_outbox("resend");
+    push_trades(&outbox, 0..10).await;
+
+    // На пятое сообщение пришла ошибка сохранения: поток завершается, клиент завершается с ошибкой
+    let res = timeout(WAIT, run(addr, &outbox, &CancellationToken::new())).await;
+    assert!(res.unwrap().unwrap().is_err());
+    assert!(outbox.stats().await.pending > 5);
+
+    // После переподключения неподтвержденные сообщения отправляются повторно, дубликаты отбрасываются
+    let shutdown = CancellationToken::new();
+    let client = run(addr, &outbox, &shutdown);
+    wait_for_trades(&storage, 10).await;
+
+    shutdown.cancel();
+    assert!(timeout(WAIT, client).await.unwrap().unwrap().is_ok());
+
+    assert_eq!(prices(&storage), (0..10).map(|p| p as f32).collect::<Vec<_>>());
+    assert!(storage.received.lock().unwrap().len() > 10);
+    assert_eq!(outbox.stats().await.pending, 0);
+    server_shutdown.cancel();
+}
+
+#[tokio::test]
+async fn skips_rejected_without_retry() {
+    let storage = Storage::default();
+    let (addr, server_shutdown, _) = start_storage(storage.clone());
+    let outbox = open_outbox("rejected");
+    push_trades(&outbox, 0..3).await;
+    outbox
+        .push(outbox::Item::Trade(grpc::incoming::Trade::from(trade(-1.0))))
+        .await;
+    push_trades(&outbox, 3..5).await;
+
+    // Отклоненное сообщение подтверждается в очереди, повторно не отправляется, отправка продолжается
+    let shutdown = CancellationToken::new();
+    let client = run(addr, &outbox, &shutdown);
+    wait_for_trades(&storage, 5).await;
+
+    shutdown.cancel();
+    assert!(timeout(WAIT, client).await.unwrap().unwrap().is_ok());
+
+    assert_eq!(prices(&storage), (0..5).map(|p| p as f32).collect::<Vec<_>>());
+    assert_eq!(storage.received.lock().unwrap().len(), 6);
+    let stats = outbox.stats().await;
+    assert_eq!((stats.pending, stats.sent, stats.rejected), (0, 6, 1));
+    server_shutdown.cancel();
+}
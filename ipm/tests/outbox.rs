@@ -0,0 +1,226 @@
+//! Тесты очереди отправки в pr на диске (client::outbox)
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+
+use ipm::client::grpc::incoming::{Candle, OrderBook, OrderBookItem, Trade};
+use ipm::client::outbox::{Item, Outbox, Record};
+use ipm::settings::Outbox as OutboxCfg;
+
+const FIGI: &str = "BBG000B9XRY4";
+const WAIT: Duration = Duration::from_secs(5);
+
+/// Настройки очереди в пустом временном каталоге (у каждого теста свой каталог)
+fn cfg(name: &str, max_size_mb: u64, segment_size_mb: u64) -> OutboxCfg {
+    let dir: PathBuf = std::env::temp_dir().join(format!("ipm-outbox-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    OutboxCfg {
+        dir: dir.to_string_lossy().to_string(),
+        max_size_mb,
+        segment_size_mb,
+    }
+}
+
+fn trade(price: f32) -> Item {
+    Item::Trade(Trade {
+        price,
+        volume: 1,
+        figi: FIGI.to_string(),
+        interval: "1min".to_string(),
+        ..Default::default()
+    })
+}
+
+fn segments(cfg: &OutboxCfg) -> usize {
+    fs::read_dir(&cfg.dir)
+        .unwrap()
+        .filter(|e| e.as_ref().unwrap().file_name().to_string_lossy().ends_with(".bin"))
+        .count()
+}
+
+async fn next(outbox: &Outbox) -> Record {
+    timeout(WAIT, outbox.next(&CancellationToken::new()))
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn keeps_order_across_kinds() {
+    let outbox = Outbox::open(&cfg("order", 1, 1)).unwrap();
+    let items = vec![
+        trade(1.0),
+        Item::OrderBook(OrderBook {
+            figi: FIGI.to_string(),
+            depth: 1,
+            bids: vec![OrderBookItem {
+                price: 100.0,
+                volume: 5,
+            }],
+            asks: vec![OrderBookItem {
+                price: 101.0,
+                volume: 3,
+            }],
+            ..Default::default()
+        }),
+        Item::Candle(Candle {
+            figi: FIGI.to_string(),
+            interval: "1min".to_string(),
+            open: 100.0,
+            close: 101.0,
+            ..Default::default()
+        }),
+        trade(2.0),
+    ];
+
+    for item in &items {
+        outbox.push(item.clone()).await;
+    }
+
+    for (sequence, item) in items.iter().enumerate() {
+        let record = next(&outbox).await;
+        assert_eq!((record.sequence, &record.item), (sequence as u64, item));
+    }
+
+    outbox.commit(3).await;
+    let stats = outbox.stats().await;
+    assert_eq!(
+        (stats.pending, stats.written, stats.sent, stats.size_bytes),
+        (0, 4, 4, 0)
+    );
+}
+
+#[tokio::test]
+async fn waits_for_items_and_finishes_on_shutdown() {
+    let outbox = Outbox::open(&cfg("wait", 1, 1)).unwrap();
+    let shutdown = CancellationToken::new();
+
+    let o = outbox.clone();
+    let s = shutdown.clone();
+    let reader = tokio::spawn(async move { o.next(&s).await.unwrap() });
+    outbox.push(trade(1.0)).await;
+    let record = timeout(WAIT, reader).await.unwrap().unwrap().unwrap();
+    assert_eq!(record.item, trade(1.0));
+
+    shutdown.cancel();
+    assert_eq!(timeout(WAIT, outbox.next(&shutdown)).await.unwrap().unwrap(), None);
+}
+
+#[tokio::test]
+async fn rewinds_to_committed_position() {
+    let outbox = Outbox::open(&cfg("rewind", 1, 1)).unwrap();
+    for price in 0..5 {
+        outbox.push(trade(price as f32)).await;
+    }
+
+    for _ in 0..4 {
+        next(&outbox).await;
+    }
+
+    // Подтверждено только первые два, соединение оборвалось: неподтвержденные будут отправлены снова
+    outbox.commit(1).await;
+    outbox.rewind().await;
+    assert_eq!(next(&outbox).await.item, trade(2.0));
+    assert_eq!(outbox.stats().await.pending, 3);
+}
+
+#[tokio::test]
+async fn resumes_after_reopen() {
+    let cfg = cfg("reopen", 1, 1);
+    {
+        let outbox = Outbox::open(&cfg).unwrap();
+        for price in 0..10 {
+            outbox.push(trade(price as f32)).await;
+        }
+
+        for _ in 0..4 {
+            next(&outbox).await;
+        }
+        outbox.commit(3).await;
+        outbox.flush().await;
+    }
+
+    let outbox = Outbox::open(&cfg).unwrap();
+    let id = outbox.id().to_string();
+    assert_eq!(outbox.stats().await.pending, 6);
+    for price in 4..10 {
+        let record = next(&outbox).await;
+        assert_eq!((record.sequence, record.item), (price, trade(price as f32)));
+    }
+    outbox.commit(9).await;
+    outbox.flush().await;
+    drop(outbox);
+
+    // Все отправлено, но нумерация и идентификатор очереди сохраняются
+    let outbox = Outbox::open(&cfg).unwrap();
+    assert_eq!(outbox.id(), id);
+    outbox.push(trade(10.0)).await;
+    assert_eq!(next(&outbox).await.sequence, 10);
+}
+
+#[tokio::test]
+async fn truncates_partial_record() {
+    let cfg = cfg("partial", 1, 1);
+    {
+        let outbox = Outbox::open(&cfg).unwrap();
+        outbox.push(trade(1.0)).await;
+        outbox.push(trade(2.0)).await;
+        outbox.flush().await;
+    }
+
+    // ipm упал во время записи: от следующего сообщения записан только заголовок
+    let segment = fs::read_dir(&cfg.dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| p.extension().is_some_and(|e| e == "bin"))
+        .unwrap();
+    OpenOptions::new()
+        .append(true)
+        .open(segment)
+        .unwrap()
+        .write_all(&[1, 2, 0, 0, 0, 0, 0, 0, 0, 100, 0, 0, 0, 8])
+        .unwrap();
+
+    let outbox = Outbox::open(&cfg).unwrap();
+    assert_eq!(outbox.stats().await.pending, 2);
+    outbox.push(trade(3.0)).await;
+    for price in 1..=3 {
+        assert_eq!(next(&outbox).await.item, trade(price as f32));
+    }
+}
+
+#[tokio::test]
+async fn drops_items_when_full_and_removes_sent_segments() {
+    let cfg = cfg("full", 2, 1);
+    let outbox = Outbox::open(&cfg).unwrap();
+
+    let count = 100_000;
+    for price in 0..count {
+        outbox.push(trade(price as f32)).await;
+    }
+
+    let stats = outbox.stats().await;
+    assert!(stats.dropped > 0);
+    assert_eq!(stats.written + stats.dropped, count);
+    assert!(stats.size_bytes <= stats.max_size_bytes);
+    assert_eq!(segments(&cfg), 2);
+
+    // Сохраненные данные не теряются и идут по порядку, отправленные сегменты удаляются
+    for price in 0..stats.written {
+        assert_eq!(next(&outbox).await.item, trade(price as f32));
+    }
+    outbox.commit(stats.written - 1).await;
+    assert_eq!(outbox.stats().await.pending, 0);
+    assert_eq!(segments(&cfg), 1);
+
+    // После отправки место освобождается
+    outbox.push(trade(1.0)).await;
+    assert_eq!(outbox.stats().await.written, stats.written + 1);
+}
@@ -0,0 +1,187 @@
+//! Интеграционные тесты grpc стрима (server::run): поведение при отставании потребителя (lag_policy)
+
+use std::net::TcpListener;
+use std::time::Duration;
+
+use chrono::Utc;
+use tokio::time::{sleep, timeout};
+use tokio_util::sync::CancellationToken;
+use tonic::transport::Channel;
+use tonic::{Code, Request, Streaming};
+
+use ipm::domain::trade::Trade;
+use ipm::server::{self, ReceiverMakers};
+use ipm::settings::Grpc;
+use ipm::subscriptions::Subscriptions;
+use shared::channel::{self, BroadcastSender};
+use shared::receiver::ReceiverMaker;
+use shared::streaming::LagPolicy;
+
+use incoming::price_stream_client::PriceStreamClient;
+use incoming::Subscription;
+use stats::stats_client::StatsClient;
+
+pub mod incoming {
+    tonic::include_proto!("incoming");
+}
+
+pub mod stats {
+    tonic::include_proto!("stats");
+}
+
+const FIGI: &str = "BBG000B9XRY4";
+const CAPACITY: usize = 4;
+const WAIT: Duration = Duration::from_secs(5);
+
+fn trade(price: f32) -> Trade {
+    let now = Utc::now();
+    Trade::new(price, 1, FIGI.to_string(), "1min".to_string(), now, now, now)
+}
+
+/// Запущенный grpc сервер с подпиской на trade стрим
+struct Subscribed {
+    sender: BroadcastSender<Trade>,
+    stream: Streaming<incoming::Trade>,
+    url: String,
+    shutdown: CancellationToken,
+}
+
+/// Запускает grpc сервер и подписывается на trade стрим
+async fn subscribe(lag_policy: LagPolicy) -> Subscribed {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let trade_sender = channel::broadcast("trade", CAPACITY);
+    let shutdown = CancellationToken::new();
+
+    server::run(
+        Grpc {
+            addr: format!("127.0.0.1:{}", port),
+            lag_policy,
+        },
+        ReceiverMakers {
+            trade: ReceiverMaker::new(trade_sender.clone()),
+            order_book: ReceiverMaker::new(channel::broadcast("order_book", CAPACITY)),
+            candle: ReceiverMaker::new(channel::broadcast("candle", CAPACITY)),
+        },
+        Subscriptions::new(&[]),
+        vec![],
+        None,
+        shutdown.clone(),
+    )
+    .await
+    .unwrap();
+
+    let url = format!("http://127.0.0.1:{}", port);
+    let mut client = loop {
+        match PriceStreamClient::<Channel>::connect(url.clone()).await {
+            Ok(client) => break client,
+            Err(_) => sleep(Duration::from_millis(10)).await,
+        }
+    };
+
+    let stream = client
+        .subscribe_to_trade(Request::new(Subscription { figis: vec![] }))
+        .await
+        .unwrap()
+        .into_inner();
+
+    Subscribed {
+        sender: trade_sender,
+        stream,
+        url,
+        shutdown,
+    }
+}
+
+/// Отправляет сразу больше сообщений, чем вмещает канал: тест однопоточный, поэтому стрим сервера не успевает их
+/// вычитать и потребитель гарантированно отстает на count - CAPACITY сообщений
+fn flood(sender: &BroadcastSender<Trade>, count: usize) {
+    for i in 0..count {
+        sender.send(trade(i as f32)).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn lagging_consumer_receives_gap_marker() {
+    let Subscribed {
+        sender,
+        mut stream,
+        shutdown,
+        ..
+    } = subscribe(LagPolicy::Gap).await;
+    flood(&sender, 10);
+
+    let marker = timeout(WAIT, stream.message()).await.unwrap().unwrap().unwrap();
+    assert_eq!(marker.dropped, 6);
+    assert!(marker.figi.is_empty());
+
+    // После маркера идут последние сообщения, которые остались в канале, и стрим продолжает работать
+    for price in 6..10 {
+        let trade = timeout(WAIT, stream.message()).await.unwrap().unwrap().unwrap();
+        assert_eq!((trade.price, trade.dropped), (price as f32, 0));
+    }
+
+    sender.send(trade(100.0)).unwrap();
+    let trade = timeout(WAIT, stream.message()).await.unwrap().unwrap().unwrap();
+    assert_eq!(trade.price, 100.0);
+
+    shutdown.cancel();
+}
+
+#[tokio::test]
+async fn lagging_consumer_is_disconnected() {
+    let Subscribed {
+        sender,
+        mut stream,
+        shutdown,
+        ..
+    } = subscribe(LagPolicy::Disconnect).await;
+    flood(&sender, 10);
+
+    let status = timeout(WAIT, stream.message()).await.unwrap().unwrap_err();
+    assert_eq!(status.code(), Code::ResourceExhausted);
+
+    shutdown.cancel();
+}
+
+#[tokio::test]
+async fn channel_stats_are_counted() {
+    let Subscribed {
+        sender,
+        mut stream,
+        url,
+        shutdown,
+    } = subscribe(LagPolicy::Gap).await;
+    flood(&sender, 10);
+    for _ in 0..5 {
+        timeout(WAIT, stream.message()).await.unwrap().unwrap().unwrap();
+    }
+
+    // Нет ни одного потребителя - сообщение отбрасывается
+    drop(stream);
+    let orphan = channel::broadcast::<Trade>("orphan", CAPACITY);
+    assert!(orphan.send(trade(1.0)).is_err());
+    assert_eq!((orphan.stats().sent(), orphan.stats().dropped()), (0, 1));
+
+    let mut client = StatsClient::connect(url).await.unwrap();
+    let channels = client
+        .get_channels(Request::new(stats::Empty {}))
+        .await
+        .unwrap()
+        .into_inner()
+        .channels;
+
+    let names: Vec<_> = channels.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["trade", "order_book", "candle"]);
+    assert_eq!(
+        channels[0],
+        stats::Channel {
+            name: "trade".to_string(),
+            capacity: CAPACITY as u64,
+            sent: 10,
+            dropped: 0,
+            lagged: 6,
+        }
+    );
+
+    shutdown.cancel();
+}
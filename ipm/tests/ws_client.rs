@@ -0,0 +1,338 @@
+//! Интеграционные тесты ws клиента (client::ws::run) против mock сервера Тинькофф (tinkoff-mock)
+
+use std::time::Duration;
+
+use chrono::NaiveTime;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+
+use ipm::client::reconnect::ConnectionMonitor;
+use ipm::client::ws::{self, WsError};
+use ipm::domain::{candle::Candle, order_book::OrderBook, trade::Trade};
+use ipm::settings::{Heartbeat, InstrumentOverride, Reconnect, Recorder, Tinkoff, TradingHours};
+use ipm::subscriptions::Subscriptions;
+use shared::channel::{self, BroadcastSender, Receiver};
+use tinkoff_mock::protocol::Request;
+use tinkoff_mock::script::{Script, Step};
+use tinkoff_mock::MockServer;
+
+const FIGI: &str = "BBG000B9XRY4";
+const TOKEN: &str = "token";
+const WAIT: Duration = Duration::from_secs(5);
+
+fn config(url: String) -> Tinkoff {
+    Tinkoff {
+        ws: url,
+        figis: vec![FIGI.to_string()],
+        token: TOKEN.to_string(),
+        candle_intervals: vec!["1min".to_string()],
+        order_book_depth: 2,
+        instruments: vec![],
+        reconnect: Reconnect {
+            initial_delay_ms: 10,
+            max_delay_ms: 100,
+            multiplier: 2.0,
+            jitter: 0.0,
+            max_attempts: 0,
+            circuit_open_secs: 1,
+        },
+        heartbeat: Heartbeat {
+            ping_interval_secs: 1,
+            timeout_secs: 2,
+            figi_stale_secs: 0,
+            trading_hours: TradingHours {
+                start: NaiveTime::from_hms(0, 0, 0),
+                end: NaiveTime::from_hms(0, 0, 0),
+            },
+        },
+        recorder: Recorder {
+            enabled: false,
+            dir: String::new(),
+            rotate_secs: 0,
+            max_file_size_mb: 0,
+        },
+    }
+}
+
+/// Запущенный ws клиент и ресиверы его потоков (без ресиверов клиент данные не отправляет)
+struct Client {
+    trade: Receiver<Trade>,
+    order_book: Receiver<OrderBook>,
+    candle: Receiver<Candle>,
+    subscriptions: Subscriptions,
+    shutdown: CancellationToken,
+    handle: JoinHandle<Result<(), WsError>>,
+}
+
+fn senders() -> (
+    BroadcastSender<Trade>,
+    BroadcastSender<OrderBook>,
+    BroadcastSender<Candle>,
+) {
+    (
+        channel::broadcast("trade", 20),
+        channel::broadcast("order_book", 20),
+        channel::broadcast("candle", 20),
+    )
+}
+
+fn start_client(cfg: Tinkoff) -> Client {
+    let (trade_sender, order_book_sender, candle_sender) = senders();
+    let (trade, order_book, candle) = (
+        trade_sender.subscribe(),
+        order_book_sender.subscribe(),
+        candle_sender.subscribe(),
+    );
+    let subscriptions = Subscriptions::new(&cfg.figis);
+    let shutdown = CancellationToken::new();
+
+    let handle = tokio::spawn(ws::run(
+        cfg,
+        subscriptions.clone(),
+        ConnectionMonitor::new("ws"),
+        trade_sender,
+        order_book_sender,
+        candle_sender,
+        shutdown.clone(),
+    ));
+
+    Client {
+        trade,
+        order_book,
+        candle,
+        subscriptions,
+        shutdown,
+        handle,
+    }
+}
+
+async fn start_server(connections: Vec<Vec<Step>>) -> MockServer {
+    let script = Script {
+        token: Some(TOKEN.to_string()),
+        connections,
+    };
+    MockServer::start("127.0.0.1:0", script).await.unwrap()
+}
+
+async fn finished(client: Client) -> Result<(), WsError> {
+    timeout(WAIT, client.handle)
+        .await
+        .expect("client is still running")
+        .unwrap()
+}
+
+fn request(event: &str, figi: &str, interval: Option<&str>, depth: Option<u32>) -> Request {
+    Request {
+        event: event.to_string(),
+        figi: figi.to_string(),
+        interval: interval.map(|i| i.to_string()),
+        depth,
+        request_id: None,
+    }
+}
+
+fn wait_and_stream() -> Vec<Step> {
+    vec![Step::WaitSubscriptions { count: 2 }, Step::Stream { interval_ms: 20 }]
+}
+
+#[tokio::test]
+async fn subscribes_and_receives_events() {
+    let server = start_server(vec![wait_and_stream()]).await;
+    let mut client = start_client(config(server.url()));
+
+    let requests = timeout(WAIT, server.wait_requests(0, 2)).await.unwrap();
+    assert_eq!(
+        requests,
+        vec![
+            request("candle:subscribe", FIGI, Some("1min"), None),
+            request("orderbook:subscribe", FIGI, None, Some(2)),
+        ]
+    );
+
+    let trade = timeout(WAIT, client.trade.recv()).await.unwrap().unwrap();
+    assert_eq!(trade.figi, FIGI);
+    assert_eq!(trade.interval, "1min");
+
+    let candle = timeout(WAIT, client.candle.recv()).await.unwrap().unwrap();
+    assert_eq!(candle.figi, FIGI);
+    assert!(candle.low <= candle.open && candle.open <= candle.high);
+
+    let order_book = timeout(WAIT, client.order_book.recv()).await.unwrap().unwrap();
+    assert_eq!(order_book.figi, FIGI);
+    assert_eq!(order_book.depth, 2);
+    assert_eq!(order_book.bids.len(), 2);
+    assert_eq!(order_book.asks.len(), 2);
+
+    client.shutdown.cancel();
+    assert!(finished(client).await.is_ok());
+}
+
+#[tokio::test]
+async fn subscribes_with_instrument_settings() {
+    let server = start_server(vec![wait_and_stream()]).await;
+    let mut cfg = config(server.url());
+    cfg.instruments = vec![InstrumentOverride {
+        figi: FIGI.to_string(),
+        candle_intervals: Some(vec!["1min".to_string(), "5min".to_string()]),
+        order_book_depth: Some(5),
+    }];
+    let client = start_client(cfg);
+
+    let requests = timeout(WAIT, server.wait_requests(0, 3)).await.unwrap();
+    assert_eq!(
+        requests,
+        vec![
+            request("candle:subscribe", FIGI, Some("1min"), None),
+            request("candle:subscribe", FIGI, Some("5min"), None),
+            request("orderbook:subscribe", FIGI, None, Some(5)),
+        ]
+    );
+
+    client.shutdown.cancel();
+    assert!(finished(client).await.is_ok());
+}
+
+#[tokio::test]
+async fn applies_subscription_changes() {
+    let server = start_server(vec![wait_and_stream()]).await;
+    let client = start_client(config(server.url()));
+    timeout(WAIT, server.wait_requests(0, 2)).await.unwrap();
+
+    client.subscriptions.subscribe(vec!["NEW".to_string()]);
+    let requests = timeout(WAIT, server.wait_requests(0, 4)).await.unwrap();
+    assert_eq!(
+        requests[2..],
+        [
+            request("candle:subscribe", "NEW", Some("1min"), None),
+            request("orderbook:subscribe", "NEW", None, Some(2)),
+        ]
+    );
+
+    client.subscriptions.unsubscribe(vec![FIGI.to_string()]);
+    let requests = timeout(WAIT, server.wait_requests(0, 6)).await.unwrap();
+    assert_eq!(
+        requests[4..],
+        [
+            request("candle:unsubscribe", FIGI, Some("1min"), None),
+            request("orderbook:unsubscribe", FIGI, None, Some(2)),
+        ]
+    );
+
+    client.shutdown.cancel();
+    assert!(finished(client).await.is_ok());
+}
+
+#[tokio::test]
+async fn skips_malformed_frames() {
+    let server = start_server(vec![vec![
+        Step::WaitSubscriptions { count: 2 },
+        Step::Raw {
+            text: "not a json".to_string(),
+        },
+        Step::Raw {
+            text: r#"{"event":"unknown","payload":{}}"#.to_string(),
+        },
+        Step::Error {
+            message: "something went wrong".to_string(),
+        },
+        Step::Stream { interval_ms: 20 },
+    ]])
+    .await;
+    let mut client = start_client(config(server.url()));
+
+    let candle = timeout(WAIT, client.candle.recv()).await.unwrap().unwrap();
+    assert_eq!(candle.figi, FIGI);
+
+    client.shutdown.cancel();
+    assert!(finished(client).await.is_ok());
+}
+
+#[tokio::test]
+async fn returns_closed_on_close_frame() {
+    let server = start_server(vec![vec![
+        Step::WaitSubscriptions { count: 2 },
+        Step::Close {
+            code: 4000,
+            reason: "bye".to_string(),
+        },
+    ]])
+    .await;
+    let client = start_client(config(server.url()));
+
+    match finished(client).await {
+        Err(err @ WsError::Closed { .. }) => {
+            assert!(matches!(&err, WsError::Closed { code: 4000, reason } if reason == "bye"));
+            assert!(!err.is_fatal());
+        }
+        res => panic!("unexpected result: {:?}", res),
+    }
+}
+
+#[tokio::test]
+async fn returns_error_on_dropped_connection() {
+    let server = start_server(vec![vec![Step::WaitSubscriptions { count: 2 }, Step::Drop]]).await;
+    let client = start_client(config(server.url()));
+
+    let err = finished(client).await.unwrap_err();
+    assert!(!err.is_fatal(), "unexpected error: {:?}", err);
+}
+
+#[tokio::test]
+async fn rejected_token_is_fatal() {
+    let server = start_server(vec![wait_and_stream()]).await;
+    let mut cfg = config(server.url());
+    cfg.token = "invalid".to_string();
+    let client = start_client(cfg);
+
+    let err = finished(client).await.unwrap_err();
+    assert!(matches!(err, WsError::Auth(status) if status == 401));
+    assert!(err.is_fatal());
+    assert_eq!(server.connections(), 0);
+}
+
+#[tokio::test]
+async fn silent_connection_is_stale() {
+    let server = start_server(vec![vec![Step::WaitSubscriptions { count: 2 }, Step::Silence]]).await;
+    let client = start_client(config(server.url()));
+
+    let err = finished(client).await.unwrap_err();
+    assert!(matches!(err, WsError::Stale(_)), "unexpected error: {:?}", err);
+}
+
+#[tokio::test]
+async fn resubscribes_after_reconnect() {
+    let server = start_server(vec![
+        vec![Step::WaitSubscriptions { count: 2 }, Step::Drop],
+        wait_and_stream(),
+    ])
+    .await;
+    let cfg = config(server.url());
+
+    let client = start_client(cfg.clone());
+    client.subscriptions.subscribe(vec!["NEW".to_string()]);
+    let subscriptions = client.subscriptions.clone();
+    assert!(finished(client).await.is_err());
+
+    // Повторный запуск, как это делает start_and_restart_ws_client, с актуальным набором инструментов
+    let (trade_sender, order_book_sender, candle_sender) = senders();
+    let mut candle = candle_sender.subscribe();
+    let shutdown = CancellationToken::new();
+    let handle = tokio::spawn(ws::run(
+        cfg,
+        subscriptions,
+        ConnectionMonitor::new("ws"),
+        trade_sender,
+        order_book_sender,
+        candle_sender,
+        shutdown.clone(),
+    ));
+
+    timeout(WAIT, server.wait_connections(2)).await.unwrap();
+    let requests = timeout(WAIT, server.wait_requests(1, 4)).await.unwrap();
+    assert!(requests.contains(&request("candle:subscribe", "NEW", Some("1min"), None)));
+    assert!(timeout(WAIT, candle.recv()).await.unwrap().is_ok());
+
+    shutdown.cancel();
+    assert!(timeout(WAIT, handle).await.unwrap().unwrap().is_ok());
+}
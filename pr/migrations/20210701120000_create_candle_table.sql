@@ -0,0 +1,6 @@
+CREATE TABLE candle (
+   id SERIAL PRIMARY KEY,
+   figi TEXT NOT NULL,
+   received TIMESTAMP NOT NULL,
+   content JSONB
+);
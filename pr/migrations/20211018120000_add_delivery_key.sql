@@ -0,0 +1,8 @@
+ALTER TABLE trade ADD COLUMN source TEXT, ADD COLUMN sequence BIGINT;
+CREATE UNIQUE INDEX trade_source_sequence_idx ON trade (source, sequence);
+
+ALTER TABLE order_book ADD COLUMN source TEXT, ADD COLUMN sequence BIGINT;
+CREATE UNIQUE INDEX order_book_source_sequence_idx ON order_book (source, sequence);
+
+ALTER TABLE candle ADD COLUMN source TEXT, ADD COLUMN sequence BIGINT;
+CREATE UNIQUE INDEX candle_source_sequence_idx ON candle (source, sequence);
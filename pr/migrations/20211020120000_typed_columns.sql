@@ -0,0 +1,61 @@
+-- Типизированные колонки вместо content JSONB для trade и order_book, время - timestamptz.
+-- Уровни книги заказов хранятся массивами (цены и объемы по сторонам, от лучшей цены).
+-- Ранее сохраненные данные переносятся из content, после чего content удаляется.
+
+ALTER TABLE trade
+    ADD COLUMN price NUMERIC,
+    ADD COLUMN volume BIGINT,
+    ADD COLUMN interval TEXT,
+    ADD COLUMN minute_rounded TIMESTAMPTZ,
+    ADD COLUMN sent TIMESTAMPTZ,
+    ALTER COLUMN received TYPE TIMESTAMPTZ USING received AT TIME ZONE 'UTC';
+
+UPDATE trade
+SET price          = (content ->> 'price')::NUMERIC,
+    volume         = (content ->> 'volume')::BIGINT,
+    interval       = COALESCE(content ->> 'interval', ''), -- в ранних данных интервала нет
+    minute_rounded = (content ->> 'minute_rounded')::TIMESTAMPTZ,
+    sent           = (content ->> 'sent')::TIMESTAMPTZ;
+
+ALTER TABLE trade
+    ALTER COLUMN price SET NOT NULL,
+    ALTER COLUMN volume SET NOT NULL,
+    ALTER COLUMN interval SET NOT NULL,
+    ALTER COLUMN minute_rounded SET NOT NULL,
+    ALTER COLUMN sent SET NOT NULL,
+    DROP COLUMN content;
+
+ALTER TABLE order_book
+    ADD COLUMN depth INT,
+    ADD COLUMN bid_prices NUMERIC[],
+    ADD COLUMN bid_volumes BIGINT[],
+    ADD COLUMN ask_prices NUMERIC[],
+    ADD COLUMN ask_volumes BIGINT[],
+    ADD COLUMN sent TIMESTAMPTZ,
+    ALTER COLUMN received TYPE TIMESTAMPTZ USING received AT TIME ZONE 'UTC';
+
+-- bids и asks в content - массивы пар [цена, объем]
+UPDATE order_book
+SET depth       = (content ->> 'depth')::INT,
+    bid_prices  = ARRAY(SELECT (l ->> 0)::NUMERIC FROM jsonb_array_elements(content -> 'bids') WITH ORDINALITY AS e(l, n) ORDER BY n),
+    bid_volumes = ARRAY(SELECT (l ->> 1)::BIGINT FROM jsonb_array_elements(content -> 'bids') WITH ORDINALITY AS e(l, n) ORDER BY n),
+    ask_prices  = ARRAY(SELECT (l ->> 0)::NUMERIC FROM jsonb_array_elements(content -> 'asks') WITH ORDINALITY AS e(l, n) ORDER BY n),
+    ask_volumes = ARRAY(SELECT (l ->> 1)::BIGINT FROM jsonb_array_elements(content -> 'asks') WITH ORDINALITY AS e(l, n) ORDER BY n),
+    sent        = (content ->> 'sent')::TIMESTAMPTZ;
+
+ALTER TABLE order_book
+    ALTER COLUMN depth SET NOT NULL,
+    ALTER COLUMN bid_prices SET NOT NULL,
+    ALTER COLUMN bid_volumes SET NOT NULL,
+    ALTER COLUMN ask_prices SET NOT NULL,
+    ALTER COLUMN ask_volumes SET NOT NULL,
+    ALTER COLUMN sent SET NOT NULL,
+    DROP COLUMN content;
+
+-- candle остается в JSONB, но время получения тоже timestamptz, чтобы все таблицы читались одинаково
+ALTER TABLE candle
+    ALTER COLUMN received TYPE TIMESTAMPTZ USING received AT TIME ZONE 'UTC';
+
+CREATE INDEX trade_received_idx ON trade (received);
+CREATE INDEX order_book_received_idx ON order_book (received);
+CREATE INDEX candle_received_idx ON candle (received);
@@ -0,0 +1,48 @@
+-- Партиционирование trade, order_book и candle по дням (received, сутки по UTC).
+-- Таблица пересоздается как партиционированная, партиции создаются на каждый день с данными и до сегодняшнего дня,
+-- данные переносятся, id продолжают ту же последовательность. Последующие партиции создает pr (db::partitions).
+-- Первичный ключ и уникальный индекс для дедупликации должны включать ключ партиционирования (received):
+-- повторно доставленное сообщение приходит с тем же received, поэтому дедупликация не меняется.
+-- Default партиция принимает данные, для которых нет партиции (например, за уже удаленный день).
+
+CREATE SCHEMA IF NOT EXISTS archive; -- сюда переносятся старые партиции (retention.action: archive)
+
+DO
+$$
+DECLARE
+    t         TEXT;
+    d         DATE;
+    first_day DATE;
+    last_day  DATE;
+    today     DATE := (now() AT TIME ZONE 'UTC')::DATE;
+BEGIN
+    FOREACH t IN ARRAY ARRAY ['trade', 'order_book', 'candle']
+        LOOP
+            EXECUTE format('CREATE TABLE %I (LIKE %I INCLUDING DEFAULTS) PARTITION BY RANGE (received)', t || '_new', t);
+
+            EXECUTE format('SELECT min(received AT TIME ZONE ''UTC'')::DATE, max(received AT TIME ZONE ''UTC'')::DATE FROM %I', t)
+                INTO first_day, last_day;
+
+            d := COALESCE(first_day, today);
+            WHILE d <= GREATEST(last_day, today)
+                LOOP
+                    EXECUTE format('CREATE TABLE %I PARTITION OF %I FOR VALUES FROM (%L) TO (%L)',
+                                   t || '_p' || to_char(d, 'YYYYMMDD'), t || '_new',
+                                   d::TIMESTAMP AT TIME ZONE 'UTC', (d + 1)::TIMESTAMP AT TIME ZONE 'UTC');
+                    d := d + 1;
+                END LOOP;
+            EXECUTE format('CREATE TABLE %I PARTITION OF %I DEFAULT', t || '_default', t || '_new');
+
+            EXECUTE format('INSERT INTO %I SELECT * FROM %I', t || '_new', t);
+
+            EXECUTE format('ALTER SEQUENCE %I OWNED BY NONE', t || '_id_seq');
+            EXECUTE format('DROP TABLE %I', t);
+            EXECUTE format('ALTER TABLE %I RENAME TO %I', t || '_new', t);
+            EXECUTE format('ALTER SEQUENCE %I OWNED BY %I.id', t || '_id_seq', t);
+
+            EXECUTE format('ALTER TABLE %I ADD PRIMARY KEY (id, received)', t);
+            EXECUTE format('CREATE UNIQUE INDEX %I ON %I (source, sequence, received)', t || '_source_sequence_idx', t);
+            EXECUTE format('CREATE INDEX %I ON %I (received)', t || '_received_idx', t);
+        END LOOP;
+END
+$$;
@@ -7,12 +7,27 @@ const MIGRATIONS: &str = "migrations";
 const STORING: &str = "storing";
 const READING: &str = "reading";
 const SPEED: &str = "speed";
+const SESSION: &str = "session";
+const TO: &str = "to";
+const SKIP_GAPS: &str = "skip-gaps";
+const CLEANUP: &str = "cleanup";
+const SOURCE: &str = "source";
 
 pub struct Args(ArgMatches);
 
 pub enum Mode {
     Storing,
-    Reading { date: String, speed: u16 },
+    Reading {
+        start: String,
+        end: Option<String>,
+        session: bool,
+        skip_gaps: Option<u64>,
+        speed: u16,
+    },
+    Cleanup {
+        date: String,
+        source: Option<String>,
+    },
 }
 
 impl Args {
@@ -40,17 +55,52 @@ impl Args {
                     .long(STORING)
                     .value_name("STORING MODE")
                     .takes_value(false)
-                    .conflicts_with_all(&[READING, SPEED])
+                    .conflicts_with_all(&[READING, SPEED, SESSION, TO, SKIP_GAPS, CLEANUP])
                     .about("sets a storing mode"),
             )
             .arg(
                 Arg::new(READING)
                     .short('r')
                     .long(READING)
-                    .value_name("READING MODE")
-                    .conflicts_with(STORING)
+                    .value_name("START")
+                    .conflicts_with_all(&[STORING, CLEANUP])
                     .requires(SPEED)
-                    .about("sets a reading mode"),
+                    .about("sets a reading mode from the date (YYYY-MM-DD) or datetime (YYYY-MM-DDTHH:MM[:SS])"),
+            )
+            .arg(
+                Arg::new(SESSION)
+                    .long(SESSION)
+                    .takes_value(false)
+                    .requires(READING)
+                    .about("replays trading sessions instead of calendar days (with -r)"),
+            )
+            .arg(
+                Arg::new(TO)
+                    .long(TO)
+                    .value_name("END")
+                    .requires(READING)
+                    .about("sets the end of replay: the last date or datetime (with -r)"),
+            )
+            .arg(
+                Arg::new(SKIP_GAPS)
+                    .long(SKIP_GAPS)
+                    .value_name("SECONDS")
+                    .requires(READING)
+                    .about("skips pauses longer than SECONDS instead of sleeping (with -r)"),
+            )
+            .arg(
+                Arg::new(CLEANUP)
+                    .long(CLEANUP)
+                    .value_name("DATE")
+                    .conflicts_with_all(&[STORING, READING])
+                    .about("deletes data for the date (YYYY-MM-DD) and exits"),
+            )
+            .arg(
+                Arg::new(SOURCE)
+                    .long(SOURCE)
+                    .value_name("SOURCE")
+                    .requires(CLEANUP)
+                    .about("deletes only data of the source (with --cleanup)"),
             )
             .arg(
                 Arg::new(SPEED)
@@ -76,15 +126,49 @@ impl Args {
             return Ok(Mode::Storing);
         }
 
-        // В случае режима чтения мы указываем не только флаг, но и дату, для которой запускаем этот режим
-        let date = self
+        // Очистка данных за дату (всех или только одного источника) - отдельная административная команда
+        if let Some(date) = self.value_of(CLEANUP) {
+            let source = self.value_of(SOURCE).map(|s| s.to_string());
+            return Ok(Mode::Cleanup {
+                date: date.to_string(),
+                source,
+            });
+        }
+
+        // В случае режима чтения мы указываем не только флаг, но и начало (дату или время), с которого запускаем режим
+        let start = self
             .value_of(READING)
-            .context("startup mode not defined, must be specified -s or -r (storing or reading)")?
+            .context("startup mode not defined, must be specified -s, -r or --cleanup (storing, reading or cleanup)")?
             .to_string();
 
+        let end = self.value_of(TO).map(|s| s.to_string());
+        let session = self.is_present(SESSION);
+        let skip_gaps = self.get_skip_gaps()?;
         let speed = self.get_speed()?;
 
-        Ok(Mode::Reading { date, speed })
+        Ok(Mode::Reading {
+            start,
+            end,
+            session,
+            skip_gaps,
+            speed,
+        })
+    }
+
+    fn get_skip_gaps(&self) -> anyhow::Result<Option<u64>> {
+        let skip_gaps = match self.value_of(SKIP_GAPS) {
+            Some(s) => s,
+            None => return Ok(None),
+        };
+        let result = skip_gaps
+            .parse::<u64>()
+            .context("skip gaps must by only unsigned integer (seconds)")?;
+
+        if result == 0 {
+            return Err(anyhow!("skip gaps must be greater than zero"));
+        }
+
+        Ok(Some(result))
     }
 
     fn get_speed(&self) -> anyhow::Result<u16> {
@@ -0,0 +1,55 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::NaiveDate;
+use log::info;
+use sqlx::pool::Pool;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{ConnectOptions, Postgres};
+
+use super::period::Period;
+use super::queries::{delete_candles, delete_order_books, delete_trades};
+use crate::settings::Exchange;
+
+/// Административная очистка (pr --cleanup): удаляет данные за сутки date (по времени биржи), если задан source -
+/// только данные этого источника, и завершается. Сервер при этом не запускается.
+pub async fn run(db_url: String, exchange: Exchange, date: NaiveDate, source: Option<String>) -> anyhow::Result<()> {
+    let mut options = PgConnectOptions::from_str(db_url.as_str())?;
+    options.disable_statement_logging();
+    let pool = PgPoolOptions::new()
+        .max_connections(1)
+        .connect_timeout(Duration::from_secs(1))
+        .connect_with(options)
+        .await
+        .context("db connect failed")?;
+
+    delete_day(&pool, &exchange, date, source.as_deref()).await?;
+    pool.close().await;
+
+    Ok(())
+}
+
+/// Удаление данных за сутки из всех таблиц, возвращает количество удаленных строк
+pub(super) async fn delete_day(
+    pool: &Pool<Postgres>,
+    exchange: &Exchange,
+    date: NaiveDate,
+    source: Option<&str>,
+) -> anyhow::Result<u64> {
+    let (begin, end) = Period::Days(date, date).interval(pool, exchange).await?;
+    let trades = delete_trades(pool, begin, end, source).await?;
+    let order_books = delete_order_books(pool, begin, end, source).await?;
+    let candles = delete_candles(pool, begin, end, source).await?;
+
+    info!(
+        "data for {} deleted (source: {}), trades: {}, order books: {}, candles: {}",
+        date,
+        source.unwrap_or("all"),
+        trades,
+        order_books,
+        candles
+    );
+
+    Ok(trades + order_books + candles)
+}
@@ -1,3 +1,6 @@
+pub mod cleanup;
+pub(super) mod partitions;
+pub mod period;
 pub(super) mod queries;
 pub mod reading;
 pub mod storing;
@@ -0,0 +1,112 @@
+use anyhow::Context;
+use chrono::{Duration, NaiveDate, Utc};
+use log::{error, info};
+use sqlx::pool::Pool;
+use sqlx::Postgres;
+use tokio::time::{interval_at, Instant};
+use tokio_util::sync::CancellationToken;
+
+use super::queries::select_partitions;
+use crate::settings::{Partitions, RetentionAction};
+
+/// Партиционированные таблицы (см. миграцию partition_by_day)
+const TABLES: [&str; 3] = ["trade", "order_book", "candle"];
+const ARCHIVE_SCHEMA: &str = "archive";
+const DATE_FORMAT: &str = "%Y%m%d";
+
+/// Обслуживание партиций: создание на сегодня и premake_days дней вперед, затем политика хранения (retention).
+/// Выполняется при запуске в режиме сохранения (ошибка останавливает запуск) и затем по расписанию (см. schedule).
+pub async fn maintain(pool: &Pool<Postgres>, cfg: &Partitions) -> anyhow::Result<()> {
+    let today = Utc::today().naive_utc();
+
+    for table in TABLES.iter() {
+        for days in 0..=cfg.premake_days {
+            create(pool, table, today + Duration::days(days as i64))
+                .await
+                .with_context(|| format!("could not create partition of {}", table))?;
+        }
+
+        if cfg.retention.days > 0 {
+            let oldest = today - Duration::days(cfg.retention.days as i64);
+            retain(pool, table, oldest, cfg.retention.action)
+                .await
+                .with_context(|| format!("could not apply retention policy to {}", table))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Раз в check_interval_secs, ошибки только пишутся в лог (при следующей проверке попытка повторится)
+pub fn schedule(pool: Pool<Postgres>, cfg: Partitions, shutdown: CancellationToken) {
+    tokio::spawn(async move {
+        let period = std::time::Duration::from_secs(cfg.check_interval_secs);
+        let mut checks = interval_at(Instant::now() + period, period);
+
+        loop {
+            tokio::select! {
+                _ = checks.tick() => {
+                    if let Err(err) = maintain(&pool, &cfg).await {
+                        error!("partitions maintenance failed: {:?}", err);
+                    }
+                },
+                _ = shutdown.cancelled() => return,
+            }
+        }
+    });
+}
+
+/// Партиция на сутки date (UTC), если ее еще нет
+async fn create(pool: &Pool<Postgres>, table: &str, date: NaiveDate) -> anyhow::Result<()> {
+    let begin = date.and_hms(0, 0, 0);
+    let end = begin + Duration::days(1);
+    let sql = format!(
+        "CREATE TABLE IF NOT EXISTS {} PARTITION OF {} FOR VALUES FROM ('{}+00') TO ('{}+00')",
+        partition_name(table, date),
+        table,
+        begin,
+        end
+    );
+
+    sqlx::query(&sql).execute(pool).await?;
+    Ok(())
+}
+
+/// Партиции за дни раньше oldest удаляются или переносятся в схему archive
+async fn retain(pool: &Pool<Postgres>, table: &str, oldest: NaiveDate, action: RetentionAction) -> anyhow::Result<()> {
+    for name in select_partitions(pool, table).await? {
+        let date = match partition_date(table, &name) {
+            Some(date) if date < oldest => date,
+            _ => continue, // свежая или default партиция
+        };
+
+        match action {
+            RetentionAction::Drop => {
+                sqlx::query(&format!("DROP TABLE {}", name)).execute(pool).await?;
+            }
+            RetentionAction::Archive => {
+                let mut tx = pool.begin().await?;
+                sqlx::query(&format!("ALTER TABLE {} DETACH PARTITION {}", table, name))
+                    .execute(&mut tx)
+                    .await?;
+                sqlx::query(&format!("ALTER TABLE {} SET SCHEMA {}", name, ARCHIVE_SCHEMA))
+                    .execute(&mut tx)
+                    .await?;
+                tx.commit().await?;
+            }
+        }
+
+        info!("partition {} for {} is retired: {:?}", name, date, action);
+    }
+
+    Ok(())
+}
+
+fn partition_name(table: &str, date: NaiveDate) -> String {
+    format!("{}_p{}", table, date.format(DATE_FORMAT))
+}
+
+fn partition_date(table: &str, name: &str) -> Option<NaiveDate> {
+    let suffix = name.strip_prefix(table)?.strip_prefix("_p")?;
+    NaiveDate::parse_from_str(suffix, DATE_FORMAT).ok()
+}
@@ -0,0 +1,193 @@
+use std::fmt;
+
+use anyhow::{anyhow, Context};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+use sqlx::pool::Pool;
+use sqlx::Postgres;
+
+use super::queries::select_interval;
+use crate::settings::Exchange;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const DATETIME_FORMATS: [&str; 4] = [
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %H:%M",
+];
+
+/// Какие данные воспроизводить (или удалять), даты и время - по времени биржи
+#[derive(Debug, Clone, PartialEq)]
+pub enum Period {
+    /// Календарные сутки в часовом поясе биржи, с первого по последний день включительно
+    Days(NaiveDate, NaiveDate),
+    /// От начала торговой сессии первого дня до конца сессии последнего дня
+    Sessions(NaiveDate, NaiveDate),
+    /// Произвольный интервал [begin, end)
+    Range(NaiveDateTime, NaiveDateTime),
+}
+
+/// Граница периода в командной строке: дата или дата со временем
+enum Point {
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+}
+
+impl Period {
+    /// Разбор границ из командной строки: start и end - дата (YYYY-MM-DD) или дата со временем (YYYY-MM-DDTHH:MM[:SS]).
+    /// Если end не указан, воспроизводится один день start. Дата в end означает конец этого дня (или его сессии).
+    pub fn parse(start: &str, end: Option<&str>, session: bool) -> anyhow::Result<Self> {
+        let start = parse_point(start)?;
+        let end = end.map(parse_point).transpose()?;
+
+        let period = match (start, end, session) {
+            (Point::Date(first), None, false) => Period::Days(first, first),
+            (Point::Date(first), None, true) => Period::Sessions(first, first),
+            (Point::Date(first), Some(Point::Date(last)), false) => Period::Days(first, last),
+            (Point::Date(first), Some(Point::Date(last)), true) => Period::Sessions(first, last),
+            (_, _, true) => return Err(anyhow!("trading sessions are selected by dates only, without time")),
+            (Point::DateTime(_), None, _) => return Err(anyhow!("the end of the period must be specified")),
+            (start, Some(end), _) => {
+                let begin = match start {
+                    Point::Date(date) => date.and_hms(0, 0, 0),
+                    Point::DateTime(dt) => dt,
+                };
+                let end = match end {
+                    Point::Date(date) => date.and_hms(0, 0, 0) + Duration::days(1),
+                    Point::DateTime(dt) => dt,
+                };
+                Period::Range(begin, end)
+            }
+        };
+
+        let valid = match &period {
+            Period::Days(first, last) | Period::Sessions(first, last) => first <= last,
+            Period::Range(begin, end) => begin < end,
+        };
+        if !valid {
+            return Err(anyhow!("the end of the period must be after its start"));
+        }
+
+        Ok(period)
+    }
+
+    /// Границы [begin, end) в UTC. Перевод из времени биржи выполняет база, с учетом перехода на летнее время.
+    pub async fn interval(
+        &self,
+        pool: &Pool<Postgres>,
+        exchange: &Exchange,
+    ) -> anyhow::Result<(DateTime<Utc>, DateTime<Utc>)> {
+        let (begin, end) = match self {
+            Period::Days(first, last) => (first.and_hms(0, 0, 0), last.and_hms(0, 0, 0) + Duration::days(1)),
+            Period::Sessions(first, last) => {
+                let begin = first.and_time(exchange.session.start);
+                let mut end = last.and_time(exchange.session.end);
+                if exchange.session.end <= exchange.session.start {
+                    end += Duration::days(1);
+                }
+                (begin, end)
+            }
+            Period::Range(begin, end) => (*begin, *end),
+        };
+
+        select_interval(pool, begin, end, &exchange.timezone).await
+    }
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Period::Days(first, last) if first == last => write!(f, "day {}", first),
+            Period::Days(first, last) => write!(f, "days {} - {}", first, last),
+            Period::Sessions(first, last) if first == last => write!(f, "session {}", first),
+            Period::Sessions(first, last) => write!(f, "sessions {} - {}", first, last),
+            Period::Range(begin, end) => write!(f, "{} - {}", begin, end),
+        }
+    }
+}
+
+fn parse_point(s: &str) -> anyhow::Result<Point> {
+    if let Ok(date) = NaiveDate::parse_from_str(s, DATE_FORMAT) {
+        return Ok(Point::Date(date));
+    }
+
+    DATETIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+        .map(Point::DateTime)
+        .with_context(|| format!("{} must be in format YYYY-MM-DD or YYYY-MM-DDTHH:MM[:SS]", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd(2021, 7, d)
+    }
+
+    fn error(start: &str, end: Option<&str>, session: bool) -> String {
+        Period::parse(start, end, session).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn parses_days_and_sessions() {
+        let parse = |end, session| Period::parse("2021-07-01", end, session).unwrap();
+
+        assert_eq!(parse(None, false), Period::Days(date(1), date(1)));
+        assert_eq!(parse(None, true), Period::Sessions(date(1), date(1)));
+        assert_eq!(parse(Some("2021-07-03"), false), Period::Days(date(1), date(3)));
+        assert_eq!(parse(Some("2021-07-03"), true), Period::Sessions(date(1), date(3)));
+        assert_eq!(parse(Some("2021-07-01"), false), Period::Days(date(1), date(1)));
+    }
+
+    #[test]
+    fn parses_ranges() {
+        let parse = |start, end| Period::parse(start, Some(end), false).unwrap();
+
+        assert_eq!(
+            parse("2021-07-01T10:00", "2021-07-01 18:45:30"),
+            Period::Range(date(1).and_hms(10, 0, 0), date(1).and_hms(18, 45, 30))
+        );
+        // Дата в начале - начало дня, в конце - конец дня
+        assert_eq!(
+            parse("2021-07-01", "2021-07-02T12:00:00"),
+            Period::Range(date(1).and_hms(0, 0, 0), date(2).and_hms(12, 0, 0))
+        );
+        assert_eq!(
+            parse("2021-07-01 12:00", "2021-07-02"),
+            Period::Range(date(1).and_hms(12, 0, 0), date(3).and_hms(0, 0, 0))
+        );
+    }
+
+    #[test]
+    fn sessions_are_selected_by_dates_only() {
+        let expected = "trading sessions are selected by dates only, without time";
+        assert_eq!(error("2021-07-01T10:00", None, true), expected);
+        assert_eq!(error("2021-07-01T10:00", Some("2021-07-02"), true), expected);
+        assert_eq!(error("2021-07-01", Some("2021-07-02T10:00"), true), expected);
+    }
+
+    #[test]
+    fn range_needs_end() {
+        assert_eq!(
+            error("2021-07-01T10:00", None, false),
+            "the end of the period must be specified"
+        );
+    }
+
+    #[test]
+    fn end_must_be_after_start() {
+        let expected = "the end of the period must be after its start";
+        assert_eq!(error("2021-07-02", Some("2021-07-01"), false), expected);
+        assert_eq!(error("2021-07-02", Some("2021-07-01"), true), expected);
+        assert_eq!(error("2021-07-01T10:00", Some("2021-07-01T10:00"), false), expected);
+        assert_eq!(error("2021-07-03T00:00", Some("2021-07-02"), false), expected);
+    }
+
+    #[test]
+    fn rejects_unknown_format() {
+        assert!(error("01.07.2021", None, false).contains("YYYY-MM-DD"));
+        assert!(error("2021-07-01", Some("2021-07-01T25:00"), false).contains("2021-07-01T25:00"));
+    }
+}
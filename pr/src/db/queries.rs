@@ -1,84 +1,312 @@
-use chrono::{Duration, DurationRound, NaiveDate, NaiveDateTime, Utc};
-use sqlx::{pool::Pool, types::Json, Postgres};
+use std::collections::HashSet;
 
-use crate::domain::order_book::OrderBook as DomainOrderBook;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use futures::stream::{BoxStream, StreamExt, TryStreamExt};
+use sqlx::{pool::Pool, types::Json, PgConnection, Postgres};
+
+use crate::domain::candle::Candle as DomainCandle;
+use crate::domain::delivery::Delivery;
+use crate::domain::order_book::{Glass, OrderBook as DomainOrderBook};
 use crate::domain::trade::Trade as DomainTrade;
 
-use super::reading::get_interval;
+/// Вставка пачки сделок одним запросом, для каждой возвращает false, если сообщение с таким ключом уже сохранено
+/// (повторная доставка). Цены передаются строками и сохраняются в NUMERIC без потери точности.
+pub async fn add_trades(pool: &Pool<Postgres>, batch: &[Delivery<DomainTrade>]) -> anyhow::Result<Vec<bool>> {
+    let k = Keys::new(batch);
+    let recs = sqlx::query!(
+        r#"
+INSERT
+INTO trade (figi, price, volume, interval, minute_rounded, sent, received, source, sequence)
+SELECT figi, price::NUMERIC, volume, interval, minute_rounded, sent, received, source, sequence
+FROM UNNEST($1::TEXT[], $2::TEXT[], $3::BIGINT[], $4::TEXT[], $5::TIMESTAMPTZ[], $6::TIMESTAMPTZ[], $7::TIMESTAMPTZ[], $8::TEXT[], $9::BIGINT[])
+    AS t (figi, price, volume, interval, minute_rounded, sent, received, source, sequence)
+ON CONFLICT (source, sequence, received) DO NOTHING
+RETURNING source, sequence
+        "#,
+        &column(batch, |t| t.figi.clone()),
+        &column(batch, |t| t.price.to_string()),
+        &column(batch, |t| t.volume as i64),
+        &column(batch, |t| t.interval.clone()),
+        &column(batch, |t| t.minute_rounded),
+        &column(batch, |t| t.sent),
+        &column(batch, |t| t.received),
+        &k.source as _,
+        &k.sequence as _
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(inserted(batch, recs.into_iter().map(|r| (r.source, r.sequence))))
+}
 
-pub async fn add_trade(pool: &Pool<Postgres>, trade: DomainTrade) -> anyhow::Result<i32> {
-    let rec = sqlx::query!(
+/// Уровни книги заказов передаются литералами массивов (по строке на книгу заказов), так как UNNEST
+/// разворачивает многомерные массивы целиком
+pub async fn add_order_books(pool: &Pool<Postgres>, batch: &[Delivery<DomainOrderBook>]) -> anyhow::Result<Vec<bool>> {
+    let k = Keys::new(batch);
+    let recs = sqlx::query!(
         r#"
 INSERT
-INTO trade (figi, received, content)
-VALUES ($1, $2, $3)
-RETURNING id
+INTO order_book (figi, depth, bid_prices, bid_volumes, ask_prices, ask_volumes, sent, received, source, sequence)
+SELECT figi, depth, bid_prices::NUMERIC[], bid_volumes::BIGINT[], ask_prices::NUMERIC[], ask_volumes::BIGINT[], sent, received, source, sequence
+FROM UNNEST($1::TEXT[], $2::INT[], $3::TEXT[], $4::TEXT[], $5::TEXT[], $6::TEXT[], $7::TIMESTAMPTZ[], $8::TIMESTAMPTZ[], $9::TEXT[], $10::BIGINT[])
+    AS ob (figi, depth, bid_prices, bid_volumes, ask_prices, ask_volumes, sent, received, source, sequence)
+ON CONFLICT (source, sequence, received) DO NOTHING
+RETURNING source, sequence
         "#,
-        trade.figi.clone(),
-        trade.received.naive_utc(),
-        Json(trade) as _
+        &column(batch, |ob| ob.figi.clone()),
+        &column(batch, |ob| ob.depth as i32),
+        &column(batch, |ob| prices(&ob.bids)),
+        &column(batch, |ob| volumes(&ob.bids)),
+        &column(batch, |ob| prices(&ob.asks)),
+        &column(batch, |ob| volumes(&ob.asks)),
+        &column(batch, |ob| ob.sent),
+        &column(batch, |ob| ob.received),
+        &k.source as _,
+        &k.sequence as _
     )
-    .fetch_one(pool)
+    .fetch_all(pool)
     .await?;
 
-    Ok(rec.id)
+    Ok(inserted(batch, recs.into_iter().map(|r| (r.source, r.sequence))))
 }
 
-pub async fn add_order_book(pool: &Pool<Postgres>, order_book: DomainOrderBook) -> anyhow::Result<i32> {
-    let rec = sqlx::query!(
+pub async fn add_candles(pool: &Pool<Postgres>, batch: &[Delivery<DomainCandle>]) -> anyhow::Result<Vec<bool>> {
+    let k = Keys::new(batch);
+    let recs = sqlx::query!(
         r#"
 INSERT
-INTO order_book (figi, received, content)
-VALUES ($1, $2, $3)
-RETURNING id
+INTO candle (figi, received, content, source, sequence)
+SELECT * FROM UNNEST($1::TEXT[], $2::TIMESTAMPTZ[], $3::JSONB[], $4::TEXT[], $5::BIGINT[])
+ON CONFLICT (source, sequence, received) DO NOTHING
+RETURNING source, sequence
         "#,
-        order_book.figi.clone(),
-        order_book.received.naive_utc(),
-        Json(order_book) as _
+        &column(batch, |c| c.figi.clone()),
+        &column(batch, |c| c.received),
+        &batch.iter().map(|d| Json(&d.item)).collect::<Vec<_>>() as _,
+        &k.source as _,
+        &k.sequence as _
     )
-    .fetch_one(pool)
+    .fetch_all(pool)
     .await?;
 
-    Ok(rec.id)
+    Ok(inserted(batch, recs.into_iter().map(|r| (r.source, r.sequence))))
+}
+
+/// Чтение данных из базы за интервал [begin, end) в порядке received (при совпадении - в порядке сохранения)
+pub trait Select: Sized {
+    fn select(conn: &mut PgConnection, begin: DateTime<Utc>, end: DateTime<Utc>)
+        -> BoxStream<'_, anyhow::Result<Self>>;
+}
+
+impl Select for DomainTrade {
+    fn select(
+        conn: &mut PgConnection,
+        begin: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> BoxStream<'_, anyhow::Result<Self>> {
+        sqlx::query!(
+            r#"
+SELECT figi, price::REAL AS "price!", volume, interval, minute_rounded, sent, received
+FROM trade
+WHERE received >= $1 AND received < $2
+ORDER BY received, id
+            "#,
+            begin,
+            end
+        )
+        .fetch(conn)
+        .map_ok(|r| DomainTrade {
+            price: r.price,
+            volume: r.volume as u64,
+            figi: r.figi,
+            interval: r.interval,
+            minute_rounded: r.minute_rounded,
+            sent: r.sent,
+            received: r.received,
+        })
+        .map_err(anyhow::Error::from)
+        .boxed()
+    }
+}
+
+impl Select for DomainOrderBook {
+    fn select(
+        conn: &mut PgConnection,
+        begin: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> BoxStream<'_, anyhow::Result<Self>> {
+        sqlx::query!(
+            r#"
+SELECT figi, depth, bid_prices::REAL[] AS "bid_prices!", bid_volumes, ask_prices::REAL[] AS "ask_prices!", ask_volumes, sent, received
+FROM order_book
+WHERE received >= $1 AND received < $2
+ORDER BY received, id
+            "#,
+            begin,
+            end
+        )
+        .fetch(conn)
+        .map_ok(|r| DomainOrderBook {
+            figi: r.figi,
+            depth: r.depth as u32,
+            bids: glass(r.bid_prices, r.bid_volumes),
+            asks: glass(r.ask_prices, r.ask_volumes),
+            sent: r.sent,
+            received: r.received,
+        })
+        .map_err(anyhow::Error::from)
+        .boxed()
+    }
+}
+
+impl Select for DomainCandle {
+    fn select(
+        conn: &mut PgConnection,
+        begin: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> BoxStream<'_, anyhow::Result<Self>> {
+        sqlx::query!(
+            r#"
+SELECT content AS "content!: Json<DomainCandle>"
+FROM candle
+WHERE received >= $1 AND received < $2
+ORDER BY received, id
+            "#,
+            begin,
+            end
+        )
+        .fetch(conn)
+        .map_ok(|r| r.content.0)
+        .map_err(anyhow::Error::from)
+        .boxed()
+    }
+}
+
+/// Колонка пачки для вставки через UNNEST
+fn column<T, V>(batch: &[Delivery<T>], value: impl Fn(&T) -> V) -> Vec<V> {
+    batch.iter().map(|d| value(&d.item)).collect()
+}
+
+/// Ключи пачки. Без ключа (source и sequence - NULL) дедупликации нет: NULL в уникальном индексе не совпадают.
+struct Keys {
+    source: Vec<Option<String>>,
+    sequence: Vec<Option<i64>>,
+}
+
+impl Keys {
+    fn new<T>(batch: &[Delivery<T>]) -> Self {
+        Keys {
+            source: batch.iter().map(|d| d.key.as_ref().map(|k| k.source.clone())).collect(),
+            sequence: batch
+                .iter()
+                .map(|d| d.key.as_ref().map(|k| k.sequence as i64))
+                .collect(),
+        }
+    }
+}
+
+/// Литерал массива цен, например {101.5,101.25}: f32 выводится в кратчайшей точной записи
+fn prices(glass: &Glass) -> String {
+    let prices: Vec<String> = glass.iter().map(|(price, _)| price.to_string()).collect();
+    format!("{{{}}}", prices.join(","))
 }
 
-pub async fn delete_today_trades(pool: &Pool<Postgres>) -> anyhow::Result<()> {
-    let today = Utc::now().duration_trunc(Duration::days(1)).unwrap().naive_utc();
-    sqlx::query!(
+fn volumes(glass: &Glass) -> String {
+    let volumes: Vec<String> = glass.iter().map(|(_, volume)| volume.to_string()).collect();
+    format!("{{{}}}", volumes.join(","))
+}
+
+fn glass(prices: Vec<f32>, volumes: Vec<i64>) -> Glass {
+    prices.into_iter().zip(volumes).map(|(p, v)| (p, v as u64)).collect()
+}
+
+/// Какие сообщения пачки вставлены: по ключам вставленных строк (RETURNING), сообщения без ключа вставляются всегда.
+/// Если в пачке одно сообщение пришло дважды, то вставлено будет одно, но оба считаются сохраненными.
+fn inserted<T>(batch: &[Delivery<T>], recs: impl Iterator<Item = (Option<String>, Option<i64>)>) -> Vec<bool> {
+    let keys: HashSet<(String, i64)> = recs.filter_map(|(source, sequence)| source.zip(sequence)).collect();
+
+    batch
+        .iter()
+        .map(|d| match &d.key {
+            Some(k) => keys.contains(&(k.source.clone(), k.sequence as i64)),
+            None => true,
+        })
+        .collect()
+}
+
+/// Удаление данных за интервал [begin, end). Если задан source, то удаляются только данные этого источника: source
+/// совпадает полностью или с именем источника до "/" (ipm отправляет данные как "<имя>/<идентификатор очереди>").
+pub async fn delete_trades(
+    pool: &Pool<Postgres>,
+    begin: DateTime<Utc>,
+    end: DateTime<Utc>,
+    source: Option<&str>,
+) -> anyhow::Result<u64> {
+    let res = sqlx::query!(
         r#"
 DELETE
 FROM trade
-WHERE received >= $1
+WHERE received >= $1 AND received < $2 AND ($3::TEXT IS NULL OR source = $3 OR split_part(source, '/', 1) = $3)
         "#,
-        today
+        begin,
+        end,
+        source
     )
     .execute(pool)
     .await?;
 
-    Ok(())
+    Ok(res.rows_affected())
 }
 
-pub async fn delete_today_order_books(pool: &Pool<Postgres>) -> anyhow::Result<()> {
-    let today = Utc::now().duration_trunc(Duration::days(1)).unwrap().naive_utc();
-    sqlx::query!(
+pub async fn delete_order_books(
+    pool: &Pool<Postgres>,
+    begin: DateTime<Utc>,
+    end: DateTime<Utc>,
+    source: Option<&str>,
+) -> anyhow::Result<u64> {
+    let res = sqlx::query!(
         r#"
 DELETE
 FROM order_book
-WHERE received >= $1
+WHERE received >= $1 AND received < $2 AND ($3::TEXT IS NULL OR source = $3 OR split_part(source, '/', 1) = $3)
         "#,
-        today
+        begin,
+        end,
+        source
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(res.rows_affected())
+}
+
+pub async fn delete_candles(
+    pool: &Pool<Postgres>,
+    begin: DateTime<Utc>,
+    end: DateTime<Utc>,
+    source: Option<&str>,
+) -> anyhow::Result<u64> {
+    let res = sqlx::query!(
+        r#"
+DELETE
+FROM candle
+WHERE received >= $1 AND received < $2 AND ($3::TEXT IS NULL OR source = $3 OR split_part(source, '/', 1) = $3)
+        "#,
+        begin,
+        end,
+        source
     )
     .execute(pool)
     .await?;
 
-    Ok(())
+    Ok(res.rows_affected())
 }
 
-pub async fn select_min_received_by_date(
+pub async fn select_min_received(
     pool: &Pool<Postgres>,
-    date: NaiveDate,
-) -> anyhow::Result<Option<NaiveDateTime>> {
-    let (begin, end) = get_interval(date);
+    begin: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> anyhow::Result<Option<DateTime<Utc>>> {
     let rec = sqlx::query!(
         r#"
 SELECT min(mc)
@@ -86,6 +314,8 @@ FROM (
     SELECT min(received) AS mc FROM trade WHERE received >= $1 AND received < $2
     UNION
     SELECT min(received) AS mc FROM order_book WHERE received >= $1 AND received < $2
+    UNION
+    SELECT min(received) AS mc FROM candle WHERE received >= $1 AND received < $2
 ) AS received
         "#,
         begin,
@@ -96,3 +326,57 @@ FROM (
 
     Ok(rec.min)
 }
+
+/// Перевод границ интервала из времени в часовом поясе timezone в UTC (база знает правила перехода на летнее время)
+pub async fn select_interval(
+    pool: &Pool<Postgres>,
+    begin: NaiveDateTime,
+    end: NaiveDateTime,
+    timezone: &str,
+) -> anyhow::Result<(DateTime<Utc>, DateTime<Utc>)> {
+    let rec = sqlx::query!(
+        r#"
+SELECT $1::TIMESTAMP AT TIME ZONE $3 AS "begin!", $2::TIMESTAMP AT TIME ZONE $3 AS "end!"
+        "#,
+        begin,
+        end,
+        timezone
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok((rec.begin, rec.end))
+}
+
+/// Текущая дата в часовом поясе timezone
+pub async fn select_today(pool: &Pool<Postgres>, timezone: &str) -> anyhow::Result<NaiveDate> {
+    let rec = sqlx::query!(
+        r#"
+SELECT (now() AT TIME ZONE $1)::DATE AS "today!"
+        "#,
+        timezone
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(rec.today)
+}
+
+/// Имена партиций таблицы (включая default)
+pub async fn select_partitions(pool: &Pool<Postgres>, table: &str) -> anyhow::Result<Vec<String>> {
+    let recs = sqlx::query!(
+        r#"
+SELECT c.relname::TEXT AS "name!"
+FROM pg_inherits i
+JOIN pg_class c ON c.oid = i.inhrelid
+JOIN pg_class p ON p.oid = i.inhparent
+WHERE p.relname = $1
+ORDER BY c.relname
+        "#,
+        table
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(recs.into_iter().map(|r| r.name).collect())
+}
@@ -1,32 +1,126 @@
-use std::fmt::Debug;
-use std::ops::Add;
 use std::str::FromStr;
 
 use anyhow::{anyhow, Context};
-use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
-use futures::TryStreamExt;
+use chrono::{DateTime, Duration, Utc};
+use futures::stream::BoxStream;
+use futures::{Stream, TryStreamExt};
 use log::{debug, error, info};
-use serde::Deserialize;
-use sqlx::pool::PoolConnection;
 use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
-use sqlx::{ConnectOptions, Postgres, Row};
-use tokio::sync::broadcast;
+use sqlx::ConnectOptions;
+use tokio::time::{sleep_until, Instant};
 use tokio_util::sync::CancellationToken;
 
-use super::queries::select_min_received_by_date;
+use super::period::Period;
+use super::queries::{select_min_received, Select};
+use crate::domain::candle::Candle as DomainCandle;
 use crate::domain::common::Received;
 use crate::domain::order_book::OrderBook as DomainOrderBook;
 use crate::domain::trade::Trade as DomainTrade;
+use crate::settings::Exchange;
+use shared::channel::BroadcastSender;
 
-const TRADE_TABLE: &str = "trade";
-const ORDER_BOOK_TABLE: &str = "order_book";
+/// Каналы, через которые данные из базы транслируются внешним потребителям (fec - for external consumers)
+pub struct FecSenders {
+    pub trade: BroadcastSender<DomainTrade>,
+    pub order_book: BroadcastSender<DomainOrderBook>,
+    pub candle: BroadcastSender<DomainCandle>,
+}
+
+/// Темп воспроизведения: speed - во сколько раз быстрее записи, паузы длиннее skip_gaps (по времени записи, например
+/// ночь между сессиями) пропускаются
+#[derive(Debug, Clone, Copy)]
+pub struct Pace {
+    pub speed: u16,
+    pub skip_gaps: Option<Duration>,
+}
+
+/// Виртуальные часы воспроизведения: событие с received отправляется в момент started + (received - origin) / speed,
+/// пропущенные перерывы вычитаются. Время отсчитывается от начала, а не от предыдущего события, поэтому задержки
+/// на отправку не накапливаются.
+struct Clock {
+    pace: Pace,
+    origin: DateTime<Utc>,
+    started: Instant,
+    skipped: Duration,
+    prev: DateTime<Utc>,
+}
+
+impl Clock {
+    fn new(pace: Pace, origin: DateTime<Utc>) -> Self {
+        Clock {
+            pace,
+            origin,
+            started: Instant::now(),
+            skipped: Duration::zero(),
+            prev: origin,
+        }
+    }
+
+    /// Ожидание прерывается при завершении: на несколько дней без пропуска перерывов оно может длиться часами
+    async fn wait(&mut self, received: DateTime<Utc>, shutdown: &CancellationToken) {
+        let deadline = self.deadline(received);
+        tokio::select! {
+            _ = sleep_until(deadline) => {},
+            _ = shutdown.cancelled() => {},
+        }
+    }
+
+    /// Момент отправки события, перерыв с предыдущего события длиннее skip_gaps пропускается
+    fn deadline(&mut self, received: DateTime<Utc>) -> Instant {
+        let gap = received - self.prev;
+        if self.pace.skip_gaps.is_some_and(|max| gap > max) {
+            info!("gap of {}s skipped: {} - {}", gap.num_seconds(), self.prev, received);
+            self.skipped = self.skipped + gap;
+        }
+        self.prev = self.prev.max(received);
+
+        let elapsed = (received - self.origin - self.skipped).num_milliseconds().max(0) as u64;
+        self.started + tokio::time::Duration::from_millis(elapsed / self.pace.speed as u64)
+    }
+}
+
+/// Событие любого типа, общий поток упорядочен по received
+#[derive(Debug)]
+enum Event {
+    Trade(DomainTrade),
+    OrderBook(DomainOrderBook),
+    Candle(DomainCandle),
+}
+
+impl Received for Event {
+    fn received(&self) -> DateTime<Utc> {
+        match self {
+            Event::Trade(trade) => trade.received(),
+            Event::OrderBook(order_book) => order_book.received(),
+            Event::Candle(candle) => candle.received(),
+        }
+    }
+}
+
+impl FecSenders {
+    fn send(&self, event: Event) {
+        match event {
+            Event::Trade(trade) => send(&self.trade, trade),
+            Event::OrderBook(order_book) => send(&self.order_book, order_book),
+            Event::Candle(candle) => send(&self.candle, candle),
+        }
+    }
+}
+
+fn send<T: Clone>(sender: &BroadcastSender<T>, item: T) {
+    if sender.receiver_count() > 0 {
+        if let Err(err) = sender.send(item) {
+            error!("send item failed, error: {}", err);
+        }
+    }
+}
 
 pub async fn run(
     db_url: String,
-    date: NaiveDate,
-    speed: u16,
-    trade_sender: broadcast::Sender<DomainTrade>,
-    order_book_sender: broadcast::Sender<DomainOrderBook>,
+    exchange: Exchange,
+    period: Period,
+    pace: Pace,
+    fec: FecSenders,
     shutdown: CancellationToken,
 ) -> anyhow::Result<()> {
     let mut options = PgConnectOptions::from_str(db_url.as_str())?;
@@ -38,126 +132,280 @@ pub async fn run(
         .await
         .context("db connect failed")?;
 
-    let dt = select_min_received_by_date(&pool, date)
+    let (begin, end) = period
+        .interval(&pool, &exchange)
+        .await
+        .context("query to select interval failed")?;
+
+    info!("replay {}: [{}, {})", period, begin, end);
+
+    let dt_start = select_min_received(&pool, begin, end)
         .await
         .context("query to select min received failed")?
         .ok_or_else(|| anyhow!("minimum datetime not found"))?;
 
-    let dt_start = DateTime::<Utc>::from_utc(dt, Utc);
     info!("datetime started: {}", dt_start);
 
-    let conn = pool
+    // Для каждой таблицы свой запрос и свое соединение, потоки объединяются (k-way merge) в один по received
+    let mut trade_conn = pool
         .acquire()
         .await
         .context("retrieves a connection from the pool failed")?;
-
-    let trade_task = reading::<DomainTrade>(
-        conn,
-        TRADE_TABLE.to_string(),
-        date,
-        dt_start,
-        speed,
-        trade_sender,
-        shutdown.clone(),
-    )
-    .await;
-
-    let conn = pool
+    let mut order_book_conn = pool
+        .acquire()
+        .await
+        .context("retrieves a connection from the pool failed")?;
+    let mut candle_conn = pool
         .acquire()
         .await
         .context("retrieves a connection from the pool failed")?;
 
-    let order_book_task = reading::<DomainOrderBook>(
-        conn,
-        ORDER_BOOK_TABLE.to_string(),
-        date,
-        dt_start,
-        speed,
-        order_book_sender,
-        shutdown.clone(),
-    )
-    .await;
+    let streams: Vec<BoxStream<anyhow::Result<Event>>> = vec![
+        Box::pin(DomainTrade::select(&mut trade_conn, begin, end).map_ok(Event::Trade)),
+        Box::pin(DomainOrderBook::select(&mut order_book_conn, begin, end).map_ok(Event::OrderBook)),
+        Box::pin(DomainCandle::select(&mut candle_conn, begin, end).map_ok(Event::Candle)),
+    ];
 
-    let _ = tokio::join!(trade_task, order_book_task);
+    let mut clock = Clock::new(pace, dt_start);
+    if let Err(err) = replay(streams, &mut clock, &fec, &shutdown).await {
+        error!("unexpected error: {:#}", err);
+    }
 
+    info!("streaming finished");
     Ok(())
 }
 
-async fn pause(prev: DateTime<Utc>, cur: DateTime<Utc>, speed: u16) {
-    let mut millis = (cur - prev).num_milliseconds() as u64;
-    millis /= speed as u64;
-    if millis > 0 {
-        tokio::time::sleep(tokio::time::Duration::from_millis(millis)).await;
+async fn replay(
+    streams: Vec<BoxStream<'_, anyhow::Result<Event>>>,
+    clock: &mut Clock,
+    fec: &FecSenders,
+    shutdown: &CancellationToken,
+) -> anyhow::Result<()> {
+    let mut events = Box::pin(merge(streams));
+
+    // все, данные закончились (это нормальный сценарий завершения)
+    while let Some(event) = events.try_next().await? {
+        debug!("event: {:?}", event);
+        clock.wait(event.received(), shutdown).await;
+        if shutdown.is_cancelled() {
+            break;
+        }
+
+        fec.send(event);
     }
+
+    Ok(())
 }
 
-/// Обобщенное решение для чтения Trade и OrderBook
-async fn reading<T: 'static + Received + Send + for<'de> Deserialize<'de> + Debug>(
-    mut conn: PoolConnection<Postgres>,
-    table: String,
-    date: NaiveDate,
-    dt_start: DateTime<Utc>,
-    speed: u16,
-    sender: broadcast::Sender<T>,
-    shutdown: CancellationToken,
-) -> tokio::task::JoinHandle<()> {
-    tokio::spawn(async move {
-        let (begin, end) = get_interval(date);
-        let mut dt_prev = dt_start;
-
-        let query = format!(
-            "SELECT content FROM {} WHERE received >= $1 AND received < $2 ORDER BY id",
-            table
-        );
+/// Слияние упорядоченных по received потоков: из голов потоков выдается самое раннее событие (см. earliest),
+/// на место выданного читается следующее событие этого потока
+fn merge<'a, T: Received + Send + 'a>(
+    mut streams: Vec<BoxStream<'a, anyhow::Result<T>>>,
+) -> impl Stream<Item = anyhow::Result<T>> + 'a {
+    async_stream::try_stream! {
+        let mut heads = Vec::with_capacity(streams.len());
+        for stream in streams.iter_mut() {
+            heads.push(stream.try_next().await?);
+        }
 
-        let mut stream = sqlx::query(query.as_str()).bind(begin).bind(end).fetch(&mut conn);
-
-        loop {
-            tokio::select! {
-                val = stream.try_next() => {
-                    match val {
-                        Ok(opt_row) => {
-                            match opt_row {
-                                Some(row) => {
-                                    let content = row.try_get("content").unwrap();
-                                    let item: T = serde_json::from_value(content).unwrap();
-
-                                    debug!("trade: {:?}", item);
-
-                                    let received = item.received();
-                                    pause(dt_prev, received, speed).await;
-                                    dt_prev = received;
-
-                                    if sender.receiver_count() > 0 {
-                                        if let Err(err) = sender.send(item) {
-                                            error!("send item failed, error: {}", err);
-                                        }
-                                    }
-                                },
-                                None => {
-                                    // все, данные закончились, выходим (это нормальный сценарий завершения)
-                                    info!("streaming finished");
-                                    return;
-                                }
-                            }
-                        },
-                        Err(err) => {
-                            error!("unexpected error: {}", err);
-                            return;
-                        }
-                    }
-                },
-                _ = shutdown.cancelled() => {
-                    info!("streaming finished");
-                    return;
-                }
-            }
+        while let Some(i) = earliest(&heads) {
+            let event = heads[i].take().expect("head is selected");
+            heads[i] = streams[i].try_next().await?;
+            yield event;
         }
-    })
+    }
 }
 
-pub(super) fn get_interval(date: NaiveDate) -> (NaiveDateTime, NaiveDateTime) {
-    let begin = date.and_hms(0, 0, 0);
-    let end = begin.add(Duration::days(1));
-    (begin, end)
+/// Номер потока с самым ранним событием в голове, при совпадении received - поток с меньшим номером,
+/// None - все потоки закончились
+fn earliest<T: Received>(heads: &[Option<T>]) -> Option<usize> {
+    heads
+        .iter()
+        .enumerate()
+        .filter_map(|(i, head)| head.as_ref().map(|event| (event.received(), i)))
+        .min()
+        .map(|(_, i)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use futures::stream::{self, StreamExt};
+
+    use super::*;
+
+    fn origin() -> DateTime<Utc> {
+        Utc.ymd(2021, 7, 1).and_hms(7, 0, 0)
+    }
+
+    /// Задержки отправки событий относительно начала воспроизведения, в секундах
+    fn delays(pace: Pace, seconds: &[i64]) -> Vec<f64> {
+        let mut clock = Clock::new(pace, origin());
+        seconds
+            .iter()
+            .map(|s| (clock.deadline(origin() + Duration::seconds(*s)) - clock.started).as_secs_f64())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_recorded_intervals() {
+        let pace = Pace {
+            speed: 1,
+            skip_gaps: None,
+        };
+        assert_eq!(delays(pace, &[0, 10, 3600, 3610]), vec![0.0, 10.0, 3600.0, 3610.0]);
+
+        let pace = Pace { speed: 10, ..pace };
+        assert_eq!(delays(pace, &[0, 10, 3600]), vec![0.0, 1.0, 360.0]);
+    }
+
+    #[test]
+    fn skips_long_gaps() {
+        let pace = Pace {
+            speed: 1,
+            skip_gaps: Some(Duration::seconds(60)),
+        };
+        // Перерыв в 60 секунд не пропускается, час пропускается целиком, отсчет продолжается с предыдущего события
+        assert_eq!(
+            delays(pace, &[0, 10, 70, 3670, 3680, 7280]),
+            vec![0.0, 10.0, 70.0, 70.0, 80.0, 80.0]
+        );
+
+        let pace = Pace { speed: 2, ..pace };
+        assert_eq!(delays(pace, &[0, 20, 3620, 3640]), vec![0.0, 10.0, 10.0, 20.0]);
+    }
+
+    #[test]
+    fn late_event_is_sent_without_waiting() {
+        let pace = Pace {
+            speed: 1,
+            skip_gaps: Some(Duration::seconds(60)),
+        };
+        // Событие раньше предыдущего не сдвигает часы и не считается перерывом
+        assert_eq!(delays(pace, &[0, 30, 20, 40]), vec![0.0, 30.0, 20.0, 40.0]);
+    }
+
+    #[tokio::test]
+    async fn wait_is_interrupted_by_shutdown() {
+        let pace = Pace {
+            speed: 1,
+            skip_gaps: None,
+        };
+        let mut clock = Clock::new(pace, origin());
+        let shutdown = CancellationToken::new();
+        shutdown.cancel();
+
+        let wait = clock.wait(origin() + Duration::hours(1), &shutdown);
+        assert!(tokio::time::timeout(std::time::Duration::from_secs(1), wait)
+            .await
+            .is_ok());
+    }
+
+    fn at(second: i64) -> DateTime<Utc> {
+        origin() + Duration::seconds(second)
+    }
+
+    fn trade(second: i64) -> Event {
+        Event::Trade(DomainTrade {
+            price: 100.0,
+            volume: 1,
+            figi: "BBG000B9XRY4".to_string(),
+            interval: "1min".to_string(),
+            minute_rounded: at(second),
+            sent: at(second),
+            received: at(second),
+        })
+    }
+
+    fn order_book(second: i64) -> Event {
+        Event::OrderBook(DomainOrderBook {
+            figi: "BBG000B9XRY4".to_string(),
+            depth: 1,
+            bids: vec![(99.0, 1)],
+            asks: vec![(101.0, 1)],
+            sent: at(second),
+            received: at(second),
+        })
+    }
+
+    fn candle(second: i64) -> Event {
+        Event::Candle(DomainCandle {
+            figi: "BBG000B9XRY4".to_string(),
+            interval: "1min".to_string(),
+            open: 100.0,
+            close: 100.0,
+            high: 100.0,
+            low: 100.0,
+            volume: 1,
+            time: at(second),
+            sent: at(second),
+            received: at(second),
+        })
+    }
+
+    fn from(events: Vec<Event>) -> BoxStream<'static, anyhow::Result<Event>> {
+        stream::iter(events.into_iter().map(Ok)).boxed()
+    }
+
+    /// Тип события и секунда received
+    fn kind(event: &Event) -> (&'static str, i64) {
+        let kind = match event {
+            Event::Trade(_) => "trade",
+            Event::OrderBook(_) => "order_book",
+            Event::Candle(_) => "candle",
+        };
+        (kind, (event.received() - origin()).num_seconds())
+    }
+
+    #[test]
+    fn earliest_head_is_selected() {
+        assert_eq!(
+            earliest(&[Some(trade(5)), Some(order_book(3)), Some(candle(4))]),
+            Some(1)
+        );
+        assert_eq!(earliest(&[None, Some(order_book(3)), Some(candle(3))]), Some(1));
+        assert_eq!(earliest(&[Some(trade(3)), None, Some(candle(3))]), Some(0));
+        assert_eq!(earliest::<Event>(&[None, None, None]), None);
+    }
+
+    #[tokio::test]
+    async fn merges_streams_by_received() {
+        let streams = vec![
+            from(vec![trade(1), trade(4), trade(4), trade(9)]),
+            from(vec![order_book(0), order_book(4), order_book(6)]),
+            from(vec![candle(2), candle(4), candle(6), candle(7), candle(8)]),
+        ];
+
+        let merged: Vec<_> = merge(streams).try_collect::<Vec<_>>().await.unwrap();
+        let kinds: Vec<_> = merged.iter().map(kind).collect();
+
+        // При совпадении received первым идет поток с меньшим номером, внутри потока порядок сохраняется
+        assert_eq!(
+            kinds,
+            vec![
+                ("order_book", 0),
+                ("trade", 1),
+                ("candle", 2),
+                ("trade", 4),
+                ("trade", 4),
+                ("order_book", 4),
+                ("candle", 4),
+                ("order_book", 6),
+                ("candle", 6),
+                ("candle", 7),
+                ("candle", 8),
+                ("trade", 9),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn merge_stops_on_error() {
+        let failed = stream::iter(vec![Ok(order_book(2)), Err(anyhow!("connection lost"))]).boxed();
+        let streams = vec![from(vec![trade(1), trade(3)]), failed, from(vec![])];
+
+        let mut merged = Box::pin(merge(streams));
+        assert_eq!(kind(&merged.try_next().await.unwrap().unwrap()), ("trade", 1));
+        assert!(merged.try_next().await.is_err());
+    }
 }
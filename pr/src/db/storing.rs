@@ -1,29 +1,38 @@
+use std::mem::take;
 use std::str::FromStr;
 use std::time::Duration;
 
-use anyhow::Context;
-use log::{error, info};
+use anyhow::{anyhow, Context};
+use log::{debug, error, info};
 use sqlx::migrate::Migrator;
 use sqlx::pool::Pool;
 use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
 use sqlx::{ConnectOptions, Postgres};
 use tokio::sync::mpsc;
+use tokio::time::interval;
 use tokio_util::sync::CancellationToken;
 
-use super::queries::{add_order_book, add_trade, delete_today_order_books, delete_today_trades};
+use super::cleanup::delete_day;
+use super::partitions;
+use super::queries::{add_candles, add_order_books, add_trades, select_today};
+use crate::domain::candle::Candle as DomainCandle;
+use crate::domain::delivery::{Delivery, Stored};
 use crate::domain::order_book::OrderBook as DomainOrderBook;
 use crate::domain::trade::Trade as DomainTrade;
+use crate::settings::{Batch, Exchange, OnStartup, DB as DbCfg};
 
 const MIGRATIONS_DEFAULT_PATH: &str = "./migrations/";
 
 pub async fn run(
-    db_url: String,
+    cfg: DbCfg,
+    exchange: Exchange,
     migrations_path: Option<&str>,
-    trade_receiver: mpsc::Receiver<DomainTrade>,
-    order_book_receiver: mpsc::Receiver<DomainOrderBook>,
+    trade_receiver: mpsc::Receiver<Delivery<DomainTrade>>,
+    order_book_receiver: mpsc::Receiver<Delivery<DomainOrderBook>>,
+    candle_receiver: mpsc::Receiver<Delivery<DomainCandle>>,
     shutdown: CancellationToken,
 ) -> anyhow::Result<()> {
-    let mut options = PgConnectOptions::from_str(db_url.as_str())?;
+    let mut options = PgConnectOptions::from_str(cfg.url.as_str())?;
     options.disable_statement_logging(); // TODO: временное решение, ждем когда допилят настройку логирования https://github.com/launchbadge/sqlx/issues/942
     let pool = PgPoolOptions::new()
         .max_connections(5)
@@ -40,39 +49,68 @@ pub async fn run(
     let m = Migrator::new(std::path::Path::new(path)).await?;
     m.run(&pool).await?;
 
-    // Запускаем очистку таблиц на сегодняшнюю дату - автоочистка удобна при многократном запуске
-    delete_today_trades(&pool).await?;
-    delete_today_order_books(&pool).await?;
+    // По умолчанию данные сохраняются, очистка за сегодня (удобна при многократном запуске) включается в настройках,
+    // сегодня - по времени биржи
+    let today = select_today(&pool, &exchange.timezone).await?;
+    match &cfg.on_startup {
+        OnStartup::Keep => info!("existing data is kept"),
+        OnStartup::DeleteToday => {
+            delete_day(&pool, &exchange, today, None).await?;
+        }
+        OnStartup::DeleteSource { source } => {
+            delete_day(&pool, &exchange, today, Some(source)).await?;
+        }
+    }
 
-    storing(pool, trade_receiver, order_book_receiver, shutdown).await;
+    // Партиции на ближайшие дни должны быть созданы до начала записи, дальше они обслуживаются по расписанию
+    partitions::maintain(&pool, &cfg.partitions).await?;
+    partitions::schedule(pool.clone(), cfg.partitions, shutdown.clone());
+
+    let receivers = (trade_receiver, order_book_receiver, candle_receiver);
+    storing(pool, receivers, cfg.batch, shutdown).await;
 
     Ok(())
 }
 
-async fn storing(
-    pool: Pool<Postgres>,
-    mut trade_receiver: mpsc::Receiver<DomainTrade>,
-    mut order_book_receiver: mpsc::Receiver<DomainOrderBook>,
-    shutdown: CancellationToken,
-) {
+type Receivers = (
+    mpsc::Receiver<Delivery<DomainTrade>>,
+    mpsc::Receiver<Delivery<DomainOrderBook>>,
+    mpsc::Receiver<Delivery<DomainCandle>>,
+);
+
+/// Сообщения копятся по типам и пишутся в базу пачками (одна вставка - одна транзакция): пачка пишется, когда набрано
+/// batch.size сообщений, а также раз в flush_interval_ms и по сигналу на завершение. Результат сообщается каждому
+/// отправителю через done после того, как вставка зафиксирована в базе.
+async fn storing(pool: Pool<Postgres>, receivers: Receivers, batch: Batch, shutdown: CancellationToken) {
+    let (mut trade_receiver, mut order_book_receiver, mut candle_receiver) = receivers;
+
     tokio::spawn(async move {
+        let mut batches = Batches::new(batch.size);
+        let mut flush = interval(Duration::from_millis(batch.flush_interval_ms));
+
         loop {
             tokio::select! {
-                val = trade_receiver.recv() => {
-                    if let Some(trade) = val {
-                        if let Err(err) = add_trade(&pool, trade).await {
-                            error!("insert trade failed: {:?}", err);
-                        }
+                Some(delivery) = trade_receiver.recv() => {
+                    batches.trades.push(delivery);
+                    if batches.trades.len() >= batch.size {
+                        batches.flush_trades(&pool).await;
                     }
                 },
-                val = order_book_receiver.recv() => {
-                    if let Some(order_book) = val {
-                        if let Err(err) = add_order_book(&pool, order_book).await {
-                            error!("insert order book failed: {:?}", err);
-                        }
+                Some(delivery) = order_book_receiver.recv() => {
+                    batches.order_books.push(delivery);
+                    if batches.order_books.len() >= batch.size {
+                        batches.flush_order_books(&pool).await;
                     }
                 },
+                Some(delivery) = candle_receiver.recv() => {
+                    batches.candles.push(delivery);
+                    if batches.candles.len() >= batch.size {
+                        batches.flush_candles(&pool).await;
+                    }
+                },
+                _ = flush.tick() => batches.flush(&pool).await,
                 _ = shutdown.cancelled() => {
+                    batches.flush(&pool).await;
                     pool.close().await;
                     info!("storing finished");
                     return;
@@ -81,3 +119,77 @@ async fn storing(
         }
     });
 }
+
+/// Накопленные для вставки сообщения по типам
+struct Batches {
+    trades: Vec<Delivery<DomainTrade>>,
+    order_books: Vec<Delivery<DomainOrderBook>>,
+    candles: Vec<Delivery<DomainCandle>>,
+}
+
+impl Batches {
+    fn new(size: usize) -> Self {
+        Batches {
+            trades: Vec::with_capacity(size),
+            order_books: Vec::with_capacity(size),
+            candles: Vec::with_capacity(size),
+        }
+    }
+
+    async fn flush(&mut self, pool: &Pool<Postgres>) {
+        self.flush_trades(pool).await;
+        self.flush_order_books(pool).await;
+        self.flush_candles(pool).await;
+    }
+
+    async fn flush_trades(&mut self, pool: &Pool<Postgres>) {
+        if !self.trades.is_empty() {
+            let batch = take(&mut self.trades);
+            let res = add_trades(pool, &batch).await;
+            report("trade", batch, res);
+        }
+    }
+
+    async fn flush_order_books(&mut self, pool: &Pool<Postgres>) {
+        if !self.order_books.is_empty() {
+            let batch = take(&mut self.order_books);
+            let res = add_order_books(pool, &batch).await;
+            report("order book", batch, res);
+        }
+    }
+
+    async fn flush_candles(&mut self, pool: &Pool<Postgres>) {
+        if !self.candles.is_empty() {
+            let batch = take(&mut self.candles);
+            let res = add_candles(pool, &batch).await;
+            report("candle", batch, res);
+        }
+    }
+}
+
+/// Результат вставки пачки сообщается каждому отправителю, при ошибке - ошибка всем сообщениям пачки
+fn report<T>(name: &str, batch: Vec<Delivery<T>>, res: anyhow::Result<Vec<bool>>) {
+    let stored: Vec<Stored> = match res {
+        Ok(inserted) => {
+            let skipped = inserted.iter().filter(|i| !**i).count();
+            if skipped > 0 {
+                debug!(
+                    "{} of {} {} items are already stored, skipped",
+                    skipped,
+                    batch.len(),
+                    name
+                );
+            }
+            inserted.into_iter().map(Ok).collect()
+        }
+        Err(err) => {
+            error!("insert {} batch of {} failed: {:?}", name, batch.len(), err);
+            batch.iter().map(|_| Err(anyhow!("{:#}", err))).collect()
+        }
+    };
+
+    // Отправитель мог не дождаться результата (например, клиент отключился), это не ошибка
+    for (delivery, res) in batch.into_iter().zip(stored) {
+        let _ = delivery.done.send(res);
+    }
+}
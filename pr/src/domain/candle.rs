@@ -0,0 +1,24 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::common::Received;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Candle {
+    pub figi: String,
+    pub interval: String,
+    pub open: f32,
+    pub close: f32,
+    pub high: f32,
+    pub low: f32,
+    pub volume: u64,
+    pub time: DateTime<Utc>,
+    pub sent: DateTime<Utc>,
+    pub received: DateTime<Utc>,
+}
+
+impl Received for Candle {
+    fn received(&self) -> DateTime<Utc> {
+        self.received
+    }
+}
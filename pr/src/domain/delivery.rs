@@ -0,0 +1,27 @@
+use tokio::sync::oneshot;
+
+/// Ключ для дедупликации: источник данных и номер сообщения в рамках источника
+#[derive(Debug, Clone)]
+pub struct DeliveryKey {
+    pub source: String,
+    pub sequence: u64,
+}
+
+/// Результат сохранения: true - сохранено, false - дубликат (было сохранено ранее)
+pub type Stored = anyhow::Result<bool>;
+
+/// Данные для сохранения в базу. Если задан key, то повторно отправленное сообщение второй раз не сохраняется.
+/// Через done db сообщает результат после того, как вставка зафиксирована в базе.
+#[derive(Debug)]
+pub struct Delivery<T> {
+    pub item: T,
+    pub key: Option<DeliveryKey>,
+    pub done: oneshot::Sender<Stored>,
+}
+
+impl<T> Delivery<T> {
+    pub fn new(item: T, key: Option<DeliveryKey>) -> (Self, oneshot::Receiver<Stored>) {
+        let (done, receiver) = oneshot::channel();
+        (Delivery { item, key, done }, receiver)
+    }
+}
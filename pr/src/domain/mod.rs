@@ -1,3 +1,5 @@
+pub mod candle;
 pub mod common;
+pub mod delivery;
 pub mod order_book;
 pub mod trade;
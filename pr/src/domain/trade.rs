@@ -8,6 +8,8 @@ pub struct Trade {
     pub price: f32,
     pub volume: u64,
     pub figi: String,
+    #[serde(default)] // в ранее сохраненных данных интервала нет
+    pub interval: String,
     pub minute_rounded: DateTime<Utc>,
     pub sent: DateTime<Utc>,
     pub received: DateTime<Utc>,
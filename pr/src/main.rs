@@ -1,14 +1,17 @@
-use chrono::NaiveDate;
+use anyhow::Context;
+use chrono::{Duration, NaiveDate};
 use flexi_logger::Logger;
 use log::info;
-use tokio::sync::{broadcast, mpsc};
 use tokio::time;
 use tokio_util::sync::CancellationToken;
 
 use args::{Args, Mode};
-use domain::{order_book::OrderBook, trade::Trade};
-use server::receiver::ReceiverMaker;
+use db::period::Period;
+use db::reading::{FecSenders, Pace};
+use domain::{candle::Candle, delivery::Delivery, order_book::OrderBook, trade::Trade};
+use server::{FdcSenders, ReceiverMakers};
 use settings::Settings;
+use shared::{channel, receiver::ReceiverMaker};
 
 mod args;
 mod db;
@@ -27,51 +30,84 @@ async fn main() -> anyhow::Result<()> {
         .format(flexi_logger::colored_detailed_format)
         .start()?;
 
-    info!("price repository started");
+    info!("price repository started, env: {:?}", cfg.env);
+
+    // Административная команда: очистка данных и выход, сервер не запускается
+    if let Mode::Cleanup { date, source } = &mode {
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").context("date must be in format YYYY-MM-DD")?;
+        return db::cleanup::run(cfg.db.url.clone(), cfg.exchange.clone(), date, source.clone()).await;
+    }
 
     let shutdown = run_ctrlc()?;
 
     // через этот канал будем транслировать данные наружу, т.е. из базы вовне, fec - for external consumers
-    let (fec_trade_sender, _) = broadcast::channel::<Trade>(20);
-    let (fec_order_book_sender, _) = broadcast::channel::<OrderBook>(20);
+    let fec_trade_sender = channel::broadcast::<Trade>("fec_trade", cfg.channels.fec.trade);
+    let fec_order_book_sender = channel::broadcast::<OrderBook>("fec_order_book", cfg.channels.fec.order_book);
+    let fec_candle_sender = channel::broadcast::<Candle>("fec_candle", cfg.channels.fec.candle);
 
     // через этот канал будем транслировать данные внутри системы, т.е. в базу, fdc - for db consumer
-    let (fdc_trade_sender, fdc_trade_receiver) = mpsc::channel::<Trade>(20);
-    let (fdc_order_book_sender, fdc_order_book_receiver) = mpsc::channel::<OrderBook>(20);
-
-    server::run(
-        cfg.server.addr,
-        ReceiverMaker::<Trade>::new(fec_trade_sender.clone()),
-        ReceiverMaker::<OrderBook>::new(fec_order_book_sender.clone()),
-        fdc_trade_sender,
-        fdc_order_book_sender,
-        shutdown.clone(),
-    )
-    .await?;
+    let (fdc_trade_sender, fdc_trade_receiver) = channel::mpsc::<Delivery<Trade>>("fdc_trade", cfg.channels.fdc.trade);
+    let (fdc_order_book_sender, fdc_order_book_receiver) =
+        channel::mpsc::<Delivery<OrderBook>>("fdc_order_book", cfg.channels.fdc.order_book);
+    let (fdc_candle_sender, fdc_candle_receiver) =
+        channel::mpsc::<Delivery<Candle>>("fdc_candle", cfg.channels.fdc.candle);
+
+    let rms = ReceiverMakers {
+        trade: ReceiverMaker::<Trade>::new(fec_trade_sender.clone()),
+        order_book: ReceiverMaker::<OrderBook>::new(fec_order_book_sender.clone()),
+        candle: ReceiverMaker::<Candle>::new(fec_candle_sender.clone()),
+    };
+
+    let fdc = FdcSenders {
+        trade: fdc_trade_sender,
+        order_book: fdc_order_book_sender,
+        candle: fdc_candle_sender,
+    };
+
+    server::run(cfg.server, rms, fdc, shutdown.clone()).await?;
 
     match mode {
         Mode::Storing => {
             db::storing::run(
-                cfg.db.url.clone(),
+                cfg.db.clone(),
+                cfg.exchange.clone(),
                 args.get_migrations_path(),
                 fdc_trade_receiver,
                 fdc_order_book_receiver,
+                fdc_candle_receiver,
                 shutdown.clone(),
             )
             .await?;
         }
-        Mode::Reading { date, speed } => {
-            let date = NaiveDate::parse_from_str(&date, "%Y-%m-%d").unwrap();
+        Mode::Reading {
+            start,
+            end,
+            session,
+            skip_gaps,
+            speed,
+        } => {
+            // Даты и время - по времени биржи: календарные дни, торговые сессии или произвольный интервал
+            let period = Period::parse(&start, end.as_deref(), session)?;
+            let pace = Pace {
+                speed,
+                skip_gaps: skip_gaps.map(|secs| Duration::seconds(secs as i64)),
+            };
+            let fec = FecSenders {
+                trade: fec_trade_sender,
+                order_book: fec_order_book_sender,
+                candle: fec_candle_sender,
+            };
             db::reading::run(
                 cfg.db.url.clone(),
-                date,
-                speed,
-                fec_trade_sender,
-                fec_order_book_sender,
+                cfg.exchange.clone(),
+                period,
+                pace,
+                fec,
                 shutdown.clone(),
             )
             .await?;
         }
+        Mode::Cleanup { .. } => unreachable!("cleanup is done before the server starts"),
     }
 
     // До этого были неблокирующие вызовы, поэтому ждем сигнала о завершении и блокируем поток
@@ -88,7 +124,7 @@ fn run_ctrlc() -> anyhow::Result<CancellationToken> {
     let token = CancellationToken::new();
     let t = token.clone();
     ctrlc::set_handler(move || {
-        let _ = t.cancel();
+        t.cancel();
     })?;
 
     Ok(token)
@@ -1,50 +1,77 @@
 use log::{error, info};
-use tokio::sync::mpsc;
+use shared::receiver::ReceiverMaker;
+use shared::statistics::{stats::stats_server::StatsServer, StatsService};
 use tokio_util::sync::CancellationToken;
 use tonic::transport::Server;
 
+use crate::domain::candle::Candle as DomainCandle;
+use crate::domain::delivery::Delivery;
 use crate::domain::order_book::OrderBook as DomainOrderBook;
 use crate::domain::trade::Trade as DomainTrade;
+use crate::settings::Server as ServerCfg;
+use shared::channel::MpscSender;
 
-use receiver::ReceiverMaker;
 use services::{
     price_storage::{storage::price_storage_server::PriceStorageServer, PriceStorageService},
     price_stream::{incoming::price_stream_server::PriceStreamServer, PriceStreamService},
 };
+use validation::Validator;
 
-pub mod receiver;
 #[macro_use]
 pub mod services;
+pub mod validation;
 
-/// Запускает два сервиса в рамках одного grpc сервера.
-/// Один сервис транслирует данные из базы наружу, а другой - извне в базу данных.
+/// Генерация ресиверов для получения данных, транслируемых наружу сервиса
+pub struct ReceiverMakers {
+    pub trade: ReceiverMaker<DomainTrade>,
+    pub order_book: ReceiverMaker<DomainOrderBook>,
+    pub candle: ReceiverMaker<DomainCandle>,
+}
+
+/// Сендеры для отправки данных в базу (fdc - for db consumer)
+pub struct FdcSenders {
+    pub trade: MpscSender<Delivery<DomainTrade>>,
+    pub order_book: MpscSender<Delivery<DomainOrderBook>>,
+    pub candle: MpscSender<Delivery<DomainCandle>>,
+}
+
+/// Запускает три сервиса в рамках одного grpc сервера.
+/// Один сервис транслирует данные из базы наружу, другой - извне в базу данных, третий отдает статистику по каналам.
 /// Напрямую к базе они не обращаются, вся работа с базой вынесена в отдельный application service (db),
 /// взаимодействие с db организовано через каналы:
-/// trade_rm - генерация trade ресиверов для получения данных транслируемых наружу сервиса,
-/// order_book_rm - генерация order book ресиверов для получения данных транслируемых наружу сервиса,
-/// fdc_trade_sender (fdc - for db consumer) - сендер для отправки trade в базу,
-/// fdc_order_book_sender (fdc - for db consumer) - сендер для отправки order book в базу.
+/// rms - генерация ресиверов (trade, order book, candle) для получения данных транслируемых наружу сервиса,
+/// fdc - сендеры (trade, order book, candle) для отправки данных в базу.
 pub async fn run(
-    addr: String,
-    trade_rm: ReceiverMaker<DomainTrade>,
-    order_book_rm: ReceiverMaker<DomainOrderBook>,
-    fdc_trade_sender: mpsc::Sender<DomainTrade>,
-    fdc_order_book_sender: mpsc::Sender<DomainOrderBook>,
+    cfg: ServerCfg,
+    rms: ReceiverMakers,
+    fdc: FdcSenders,
     shutdown: CancellationToken,
 ) -> anyhow::Result<()> {
-    let addr = addr.parse()?;
+    let addr = cfg.addr.parse()?;
     info!("grpc server listening on: {}", addr);
 
+    let stats_service = StatsService::new(vec![
+        rms.trade.stats(),
+        rms.order_book.stats(),
+        rms.candle.stats(),
+        fdc.trade.stats(),
+        fdc.order_book.stats(),
+        fdc.candle.stats(),
+    ]);
+
     // этот сервис транслирует поток данных, вычитанных из базы наружу, это нужно, чтобы воспроизводить исторические данные
-    let stream_service = PriceStreamService::new(trade_rm, order_book_rm);
+    let stream_service = PriceStreamService::new(rms.trade, rms.order_book, rms.candle, cfg.lag_policy);
 
     // этот сервис обрабатывает вызовы, которые инициируют добавление данных в базу, т.е. через его эндпоинты можно добавить данные в базу
-    let storage_service = PriceStorageService::new(fdc_trade_sender, fdc_order_book_sender);
+    // данные проверяются до отправки в базу, отклоненные данные в базу не попадают
+    let validator = Validator::new(cfg.figis);
+    let storage_service = PriceStorageService::new(fdc.trade, fdc.order_book, fdc.candle, validator);
 
     tokio::spawn(async move {
         let res = Server::builder()
             .add_service(PriceStreamServer::new(stream_service))
             .add_service(PriceStorageServer::new(storage_service))
+            .add_service(StatsServer::new(stats_service))
             .serve_with_shutdown(addr, async {
                 shutdown.cancelled().await;
                 info!("grpc server finished");
@@ -1,10 +1,19 @@
-use log::{debug, error};
-use tokio::sync::mpsc;
-use tonic::{Request, Response, Status};
+use std::pin::Pin;
+use std::sync::Arc;
 
-use incoming::{OrderBook, Trade};
+use futures::Stream;
+use log::{debug, error, info, warn};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot};
+use tonic::{Request, Response, Status, Streaming};
+
+use incoming::{Candle, OrderBook, Trade};
 use storage::price_storage_server::PriceStorage;
-use storage::Resp;
+use storage::{envelope, Ack, Code, Envelope, Resp};
+
+use crate::domain::delivery::{Delivery, DeliveryKey, Stored};
+use crate::server::validation::{Rejection, Validate, Validator};
+use shared::channel::MpscSender;
 
 // В storage есть заимствованные структуры (message) из incoming (Trade и OrderBook), поэтому incoming тоже нужно подключать
 pub mod incoming {
@@ -17,18 +26,62 @@ pub mod storage {
 
 trade_from!(incoming);
 order_book_from!(incoming);
+candle_from!(incoming);
+
+/// Сколько сообщений может ждать подтверждения сохранения в рамках одного потока
+const PENDING_CAPACITY: usize = 1024;
+
+/// Статусы в порядке важности: в ответ на поток Stream* возвращается первый, который встретился
+const SEVERITY: [Code; 4] = [
+    Code::StorageError,
+    Code::QueueFull,
+    Code::InvalidPayload,
+    Code::UnknownFigi,
+];
+
+/// Результат приема сообщения: отклонено при проверке, не принято (канал в базу переполнен или закрыт),
+/// либо отправлено в базу и ожидает результата сохранения
+enum Outcome {
+    Rejected(Rejection),
+    NotAccepted(Code, String),
+    Pending(oneshot::Receiver<Stored>),
+}
+
+impl Outcome {
+    /// Ожидание результата сохранения, дубликат (в Deliver) тоже считается принятым
+    async fn resolve(self) -> (Code, String) {
+        match self {
+            Outcome::Rejected(rejection) => (code(&rejection), rejection.to_string()),
+            Outcome::NotAccepted(code, message) => (code, message),
+            Outcome::Pending(done) => match done.await {
+                Ok(Ok(_)) => (Code::Accepted, String::new()),
+                Ok(Err(err)) => (Code::StorageError, format!("could not store: {}", err)),
+                Err(_) => (Code::StorageError, "storage is not available".to_string()),
+            },
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct PriceStorageService {
-    trade_sender: mpsc::Sender<DomainTrade>,
-    order_book_sender: mpsc::Sender<DomainOrderBook>,
+    trade_sender: MpscSender<Delivery<DomainTrade>>,
+    order_book_sender: MpscSender<Delivery<DomainOrderBook>>,
+    candle_sender: MpscSender<Delivery<DomainCandle>>,
+    validator: Arc<Validator>,
 }
 
 impl PriceStorageService {
-    pub fn new(trade_sender: mpsc::Sender<DomainTrade>, order_book_sender: mpsc::Sender<DomainOrderBook>) -> Self {
+    pub fn new(
+        trade_sender: MpscSender<Delivery<DomainTrade>>,
+        order_book_sender: MpscSender<Delivery<DomainOrderBook>>,
+        candle_sender: MpscSender<Delivery<DomainCandle>>,
+        validator: Validator,
+    ) -> Self {
         PriceStorageService {
             trade_sender,
             order_book_sender,
+            candle_sender,
+            validator: Arc::new(validator),
         }
     }
 }
@@ -41,16 +94,8 @@ impl PriceStorage for PriceStorageService {
         let trade = request.into_inner();
         debug!("extracted request: {:?}", trade);
 
-        let t = DomainTrade::from(trade);
-
-        // Отправляем через try_send для того, чтобы отработать ситуацию переполнения канала.
-        // Если принимающая сторона - db application service не успевает обработать поступающие данные,
-        // канал переполниться и мы увидим это в логах.
-        if let Err(err) = self.trade_sender.try_send(t) {
-            error!("send trade failed, error: {}", err);
-        }
-
-        Ok(Response::new(Resp::default()))
+        let outcome = submit("trade", DomainTrade::from(trade), &self.trade_sender, &self.validator);
+        Ok(Response::new(resp(outcome.resolve().await)))
     }
 
     async fn add_order_book(&self, request: Request<OrderBook>) -> Result<Response<Resp>, Status> {
@@ -59,11 +104,241 @@ impl PriceStorage for PriceStorageService {
         let order_book = request.into_inner();
         debug!("extracted request: {:?}", order_book);
 
-        let ob = DomainOrderBook::from(order_book);
-        if let Err(err) = self.order_book_sender.try_send(ob) {
-            error!("send order book failed, error: {}", err);
+        let outcome = submit(
+            "order book",
+            DomainOrderBook::from(order_book),
+            &self.order_book_sender,
+            &self.validator,
+        );
+        Ok(Response::new(resp(outcome.resolve().await)))
+    }
+
+    async fn add_candle(&self, request: Request<Candle>) -> Result<Response<Resp>, Status> {
+        debug!("request: {:?}", request);
+
+        let candle = request.into_inner();
+        debug!("extracted request: {:?}", candle);
+
+        let outcome = submit(
+            "candle",
+            DomainCandle::from(candle),
+            &self.candle_sender,
+            &self.validator,
+        );
+        Ok(Response::new(resp(outcome.resolve().await)))
+    }
+
+    async fn stream_trades(&self, request: Request<Streaming<Trade>>) -> Result<Response<Resp>, Status> {
+        ingest("trade", request.into_inner(), &self.trade_sender, &self.validator).await
+    }
+
+    async fn stream_order_books(&self, request: Request<Streaming<OrderBook>>) -> Result<Response<Resp>, Status> {
+        ingest(
+            "order book",
+            request.into_inner(),
+            &self.order_book_sender,
+            &self.validator,
+        )
+        .await
+    }
+
+    async fn stream_candles(&self, request: Request<Streaming<Candle>>) -> Result<Response<Resp>, Status> {
+        ingest("candle", request.into_inner(), &self.candle_sender, &self.validator).await
+    }
+
+    type DeliverStream = Pin<Box<dyn Stream<Item = Result<Ack, Status>> + Send + Sync + 'static>>;
+
+    /// Входящий поток читается в отдельной задаче: сообщения проверяются и передаются в базу, а ожидание результата
+    /// (pending) передается в исходящий поток, который по порядку дожидается сохранения и отправляет ответы.
+    /// Так прием следующих сообщений не ждет сохранения предыдущих. Отклоненные сообщения подтверждаются со статусом
+    /// отказа, после ошибки сохранения (STORAGE_ERROR) поток завершается, клиент отправит сообщение повторно.
+    async fn deliver(&self, request: Request<Streaming<Envelope>>) -> Result<Response<Self::DeliverStream>, Status> {
+        info!("delivery stream started");
+
+        let mut inbound = request.into_inner();
+        let (trade_sender, order_book_sender, candle_sender) = (
+            self.trade_sender.clone(),
+            self.order_book_sender.clone(),
+            self.candle_sender.clone(),
+        );
+        let validator = self.validator.clone();
+        let (pending_sender, mut pending) = mpsc::channel::<(u64, Outcome)>(PENDING_CAPACITY);
+
+        tokio::spawn(async move {
+            loop {
+                let envelope = match inbound.message().await {
+                    Ok(Some(envelope)) => envelope,
+                    Ok(None) => break,
+                    Err(status) => {
+                        warn!("delivery stream interrupted: {}", status);
+                        break;
+                    }
+                };
+
+                let sequence = envelope.sequence;
+                let key = DeliveryKey {
+                    source: envelope.source,
+                    sequence,
+                };
+
+                // Отправка в базу с ожиданием места в канале: пока база не успевает, входящий поток не читается
+                let outcome = match envelope.item {
+                    Some(envelope::Item::Trade(t)) => {
+                        deliver("trade", &trade_sender, DomainTrade::from(t), key, &validator).await
+                    }
+                    Some(envelope::Item::OrderBook(ob)) => {
+                        deliver(
+                            "order book",
+                            &order_book_sender,
+                            DomainOrderBook::from(ob),
+                            key,
+                            &validator,
+                        )
+                        .await
+                    }
+                    Some(envelope::Item::Candle(c)) => {
+                        deliver("candle", &candle_sender, DomainCandle::from(c), key, &validator).await
+                    }
+                    None => {
+                        warn!("empty envelope {} from {}, rejected", sequence, key.source);
+                        Outcome::Rejected(Rejection::InvalidPayload("envelope is empty".to_string()))
+                    }
+                };
+
+                if pending_sender.send((sequence, outcome)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let output = async_stream::stream! {
+            while let Some((sequence, outcome)) = pending.recv().await {
+                let (status, message) = outcome.resolve().await;
+                yield Ok(Ack {
+                    sequence,
+                    status: status as i32,
+                    message: message.clone(),
+                });
+
+                if status == Code::StorageError {
+                    warn!("delivery stream finished, could not store {}: {}", sequence, message);
+                    return;
+                }
+            }
+
+            info!("delivery stream finished");
+        };
+
+        Ok(Response::new(Box::pin(output) as Self::DeliverStream))
+    }
+}
+
+/// Проверка и отправка в базу без ожидания места в канале (унарные вызовы и потоки Stream*).
+/// Отправляем через try_send для того, чтобы отработать ситуацию переполнения канала.
+/// Если принимающая сторона - db application service не успевает обработать поступающие данные,
+/// канал переполниться и мы увидим это в логах, в статистике по каналам (сервис Stats, счетчик dropped) и в ответе.
+fn submit<D: Validate>(name: &str, item: D, sender: &MpscSender<Delivery<D>>, validator: &Validator) -> Outcome {
+    if let Err(rejection) = validator.check(&item) {
+        warn!("{} rejected, {}", name, rejection);
+        return Outcome::Rejected(rejection);
+    }
+
+    let (delivery, done) = Delivery::new(item, None);
+    match sender.try_send(delivery) {
+        Ok(_) => Outcome::Pending(done),
+        Err(TrySendError::Full(_)) => {
+            error!("send {} failed, channel is full", name);
+            Outcome::NotAccepted(Code::QueueFull, format!("{} is not accepted: queue is full", name))
+        }
+        Err(TrySendError::Closed(_)) => {
+            error!("send {} failed, channel is closed", name);
+            Outcome::NotAccepted(Code::StorageError, "storage is not available".to_string())
+        }
+    }
+}
+
+/// Потоковая загрузка: каждое сообщение из потока проверяется и отправляется в базу так же, как в унарных вызовах,
+/// ответ отправляется, когда клиент закроет поток и все принятые сообщения будут сохранены.
+/// Если поток оборвался, то возвращается ошибка.
+async fn ingest<P, D>(
+    name: &str,
+    mut stream: Streaming<P>,
+    sender: &MpscSender<Delivery<D>>,
+    validator: &Validator,
+) -> Result<Response<Resp>, Status>
+where
+    D: From<P> + Validate,
+{
+    info!("{} stream started", name);
+
+    // Результаты считаются по статусам в отдельной задаче по мере поступления
+    let (pending_sender, mut pending) = mpsc::channel::<Outcome>(PENDING_CAPACITY);
+    let counting = tokio::spawn(async move {
+        let mut counts = [0u64; SEVERITY.len() + 1];
+        while let Some(outcome) = pending.recv().await {
+            let (status, _) = outcome.resolve().await;
+            counts[status as usize] += 1;
         }
+        counts
+    });
+
+    while let Some(item) = stream.message().await? {
+        let _ = pending_sender
+            .send(submit(name, D::from(item), sender, validator))
+            .await;
+    }
+
+    drop(pending_sender);
+    let counts = counting
+        .await
+        .map_err(|_| Status::internal(format!("{} stream: could not count results", name)))?;
+    let message = format!(
+        "{} stream, accepted: {}, queue full: {}, invalid payload: {}, unknown figi: {}, storage error: {}",
+        name,
+        counts[Code::Accepted as usize],
+        counts[Code::QueueFull as usize],
+        counts[Code::InvalidPayload as usize],
+        counts[Code::UnknownFigi as usize],
+        counts[Code::StorageError as usize],
+    );
+    info!("{} finished", message);
+
+    match SEVERITY.iter().find(|code| counts[**code as usize] > 0) {
+        Some(code) => Ok(Response::new(resp((*code, message)))),
+        None => Ok(Response::new(Resp::default())),
+    }
+}
+
+/// Проверка и отправка в базу с ожиданием места в канале (доставка с подтверждением)
+async fn deliver<D: Validate>(
+    name: &str,
+    sender: &MpscSender<Delivery<D>>,
+    item: D,
+    key: DeliveryKey,
+    validator: &Validator,
+) -> Outcome {
+    if let Err(rejection) = validator.check(&item) {
+        warn!("{} {} from {} rejected, {}", name, key.sequence, key.source, rejection);
+        return Outcome::Rejected(rejection);
+    }
+
+    let (delivery, done) = Delivery::new(item, Some(key));
+
+    // Если канал закрыт, то done закрывается вместе с сообщением, и в ответ уйдет STORAGE_ERROR
+    let _ = sender.send(delivery).await;
+    Outcome::Pending(done)
+}
+
+fn code(rejection: &Rejection) -> Code {
+    match rejection {
+        Rejection::InvalidPayload(_) => Code::InvalidPayload,
+        Rejection::UnknownFigi(_) => Code::UnknownFigi,
+    }
+}
 
-        Ok(Response::new(Resp::default()))
+fn resp((status, message): (Code, String)) -> Resp {
+    Resp {
+        status: status as i32,
+        message,
     }
 }
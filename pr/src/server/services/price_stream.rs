@@ -1,13 +1,15 @@
 use std::pin::Pin;
 
 use futures::Stream;
-use log::{debug, error};
+use log::info;
 use tonic::{Request, Response, Status};
 
 use incoming::price_stream_server::PriceStream;
-use incoming::{Empty, OrderBook, Trade};
+use incoming::{Candle, OrderBook, Subscription, Trade};
 
-use crate::server::receiver::ReceiverMaker;
+use shared::filter::FigiFilter;
+use shared::receiver::ReceiverMaker;
+use shared::streaming::{stream, GapMarker, LagPolicy};
 
 pub mod incoming {
     tonic::include_proto!("incoming");
@@ -15,17 +17,54 @@ pub mod incoming {
 
 trade_from!(incoming);
 order_book_from!(incoming);
+candle_from!(incoming);
 
 pub struct PriceStreamService {
     trade_rm: ReceiverMaker<DomainTrade>,
     order_book_rm: ReceiverMaker<DomainOrderBook>,
+    candle_rm: ReceiverMaker<DomainCandle>,
+    lag_policy: LagPolicy,
 }
 
 impl PriceStreamService {
-    pub fn new(trade_rm: ReceiverMaker<DomainTrade>, order_book_rm: ReceiverMaker<DomainOrderBook>) -> Self {
+    pub fn new(
+        trade_rm: ReceiverMaker<DomainTrade>,
+        order_book_rm: ReceiverMaker<DomainOrderBook>,
+        candle_rm: ReceiverMaker<DomainCandle>,
+        lag_policy: LagPolicy,
+    ) -> Self {
         PriceStreamService {
             trade_rm,
             order_book_rm,
+            candle_rm,
+            lag_policy,
+        }
+    }
+}
+
+impl GapMarker for Trade {
+    fn gap(dropped: u64) -> Self {
+        Trade {
+            dropped,
+            ..Default::default()
+        }
+    }
+}
+
+impl GapMarker for OrderBook {
+    fn gap(dropped: u64) -> Self {
+        OrderBook {
+            dropped,
+            ..Default::default()
+        }
+    }
+}
+
+impl GapMarker for Candle {
+    fn gap(dropped: u64) -> Self {
+        Candle {
+            dropped,
+            ..Default::default()
         }
     }
 }
@@ -36,23 +75,13 @@ impl PriceStream for PriceStreamService {
 
     async fn subscribe_to_trade(
         &self,
-        _request: Request<Empty>,
+        request: Request<Subscription>,
     ) -> Result<Response<Self::SubscribeToTradeStream>, Status> {
-        let mut receiver = self.trade_rm.receiver();
-
-        let output = async_stream::try_stream! {
-            loop {
-                match receiver.recv().await {
-                    Ok(trade) =>  {
-                        debug!("grpc input: {:?}", trade);
-                        yield Trade::from(trade);
-                    },
-                    Err(err) => {
-                        error!("receiving trade failed: {}", err)
-                    }
-                }
-            }
-        };
+        let filter = FigiFilter::new(request.into_inner().figis);
+        info!("new trade subscription: {:?}", filter);
+
+        let receiver = self.trade_rm.receiver();
+        let output = stream("trade", receiver, filter, |t| &t.figi, self.lag_policy);
 
         Ok(Response::new(Box::pin(output) as Self::SubscribeToTradeStream))
     }
@@ -61,23 +90,29 @@ impl PriceStream for PriceStreamService {
 
     async fn subscribe_to_order_book(
         &self,
-        _request: Request<Empty>,
+        request: Request<Subscription>,
     ) -> Result<Response<Self::SubscribeToOrderBookStream>, Status> {
-        let mut receiver = self.order_book_rm.receiver();
-        let output = async_stream::try_stream! {
-            loop {
-                match receiver.recv().await {
-                    Ok(order_book) => {
-                        debug!("grpc input: {:?}", order_book.clone());
-                        yield OrderBook::from(order_book);
-                    },
-                    Err(err) => {
-                        error!("receiving order book failed: {}", err)
-                    }
-                }
-            }
-        };
+        let filter = FigiFilter::new(request.into_inner().figis);
+        info!("new order book subscription: {:?}", filter);
+
+        let receiver = self.order_book_rm.receiver();
+        let output = stream("order book", receiver, filter, |ob| &ob.figi, self.lag_policy);
 
         Ok(Response::new(Box::pin(output) as Self::SubscribeToOrderBookStream))
     }
+
+    type SubscribeToCandleStream = Pin<Box<dyn Stream<Item = Result<Candle, Status>> + Send + Sync + 'static>>;
+
+    async fn subscribe_to_candle(
+        &self,
+        request: Request<Subscription>,
+    ) -> Result<Response<Self::SubscribeToCandleStream>, Status> {
+        let filter = FigiFilter::new(request.into_inner().figis);
+        info!("new candle subscription: {:?}", filter);
+
+        let receiver = self.candle_rm.receiver();
+        let output = stream("candle", receiver, filter, |c| &c.figi, self.lag_policy);
+
+        Ok(Response::new(Box::pin(output) as Self::SubscribeToCandleStream))
+    }
 }
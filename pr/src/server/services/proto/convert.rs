@@ -29,6 +29,7 @@ macro_rules! order_book_from {
                         asks,
                         sent: item.sent.timestamp_millis(),
                         received: item.received.timestamp_millis(),
+                        dropped: 0,
                     }
                 }
             }
@@ -74,9 +75,11 @@ macro_rules! trade_from {
                         price: item.price,
                         volume: item.volume,
                         figi: item.figi,
+                        interval: item.interval,
                         minute_rounded: item.minute_rounded.timestamp_millis(),
                         sent: item.sent.timestamp_millis(),
                         received: item.received.timestamp_millis(),
+                        dropped: 0,
                     }
                 }
             }
@@ -88,6 +91,7 @@ macro_rules! trade_from {
                         price: item.price,
                         volume: item.volume,
                         figi: item.figi,
+                        interval: item.interval,
                         minute_rounded: convert_timestamp(item.minute_rounded),
                         sent: convert_timestamp(item.sent),
                         received: convert_timestamp(item.received),
@@ -97,3 +101,46 @@ macro_rules! trade_from {
         }
     };
 }
+
+macro_rules! candle_from {
+    ($p: path) => {
+        type DomainCandle = crate::domain::candle::Candle;
+        paste::paste! {
+            impl From<DomainCandle> for [<$p>]::Candle {
+                fn from(item: DomainCandle) -> Self {
+                    Self {
+                        figi: item.figi,
+                        interval: item.interval,
+                        open: item.open,
+                        close: item.close,
+                        high: item.high,
+                        low: item.low,
+                        volume: item.volume,
+                        time: item.time.timestamp_millis(),
+                        sent: item.sent.timestamp_millis(),
+                        received: item.received.timestamp_millis(),
+                        dropped: 0,
+                    }
+                }
+            }
+
+            impl From<[<$p>]::Candle> for DomainCandle {
+                fn from(item: [<$p>]::Candle) -> Self {
+                    use crate::server::services::proto::utils::convert_timestamp;
+                    DomainCandle {
+                        figi: item.figi,
+                        interval: item.interval,
+                        open: item.open,
+                        close: item.close,
+                        high: item.high,
+                        low: item.low,
+                        volume: item.volume,
+                        time: convert_timestamp(item.time),
+                        sent: convert_timestamp(item.sent),
+                        received: convert_timestamp(item.received),
+                    }
+                }
+            }
+        }
+    };
+}
@@ -0,0 +1,260 @@
+use std::fmt;
+
+use crate::domain::candle::Candle;
+use crate::domain::order_book::{Glass, OrderBook};
+use crate::domain::trade::Trade;
+use shared::filter::FigiFilter;
+
+/// Причина, по которой данные не принимаются в базу. Такие данные не будут приняты и при повторной отправке.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rejection {
+    InvalidPayload(String),
+    UnknownFigi(String),
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::InvalidPayload(reason) => write!(f, "invalid payload: {}", reason),
+            Rejection::UnknownFigi(figi) => write!(f, "unknown figi: {}", figi),
+        }
+    }
+}
+
+/// Проверка данных по содержимому, без учета списка инструментов
+pub trait Validate {
+    fn figi(&self) -> &str;
+    fn validate(&self) -> Result<(), String>;
+}
+
+/// Проверка входящих данных перед отправкой в базу: список допустимых инструментов (server.figis) и содержимое
+#[derive(Debug)]
+pub struct Validator {
+    figis: FigiFilter,
+}
+
+impl Validator {
+    pub fn new(figis: Vec<String>) -> Self {
+        Validator {
+            figis: FigiFilter::new(figis),
+        }
+    }
+
+    pub fn check<T: Validate>(&self, item: &T) -> Result<(), Rejection> {
+        if item.figi().is_empty() {
+            return Err(Rejection::InvalidPayload("figi is empty".to_string()));
+        }
+
+        if !self.figis.is_match(item.figi()) {
+            return Err(Rejection::UnknownFigi(item.figi().to_string()));
+        }
+
+        item.validate().map_err(Rejection::InvalidPayload)
+    }
+}
+
+impl Validate for Trade {
+    fn figi(&self) -> &str {
+        &self.figi
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        // volume беззнаковый (неотрицательный) уже в proto, проверять нужно только цену
+        positive("price", self.price)
+    }
+}
+
+impl Validate for OrderBook {
+    fn figi(&self) -> &str {
+        &self.figi
+    }
+
+    /// Bids - по убыванию цены, asks - по возрастанию, лучшая цена покупки ниже лучшей цены продажи.
+    /// Одна из сторон может быть пустой (например, на открытии или при остановке торгов).
+    fn validate(&self) -> Result<(), String> {
+        levels("bid", &self.bids)?;
+        levels("ask", &self.asks)?;
+
+        if self.bids.windows(2).any(|w| w[0].0 <= w[1].0) {
+            return Err("bids are not sorted in descending order".to_string());
+        }
+
+        if self.asks.windows(2).any(|w| w[0].0 >= w[1].0) {
+            return Err("asks are not sorted in ascending order".to_string());
+        }
+
+        if let (Some(bid), Some(ask)) = (self.bids.first(), self.asks.first()) {
+            if bid.0 >= ask.0 {
+                return Err(format!(
+                    "order book is crossed: best bid {} >= best ask {}",
+                    bid.0, ask.0
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Validate for Candle {
+    fn figi(&self) -> &str {
+        &self.figi
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for (name, price) in [
+            ("open", self.open),
+            ("close", self.close),
+            ("high", self.high),
+            ("low", self.low),
+        ] {
+            positive(name, price)?;
+        }
+
+        if self.low > self.high {
+            return Err(format!("low {} is greater than high {}", self.low, self.high));
+        }
+
+        Ok(())
+    }
+}
+
+fn positive(name: &str, price: f32) -> Result<(), String> {
+    if !price.is_finite() || price <= 0.0 {
+        return Err(format!("{} must be positive, got {}", name, price));
+    }
+
+    Ok(())
+}
+
+fn levels(side: &str, glass: &Glass) -> Result<(), String> {
+    for (price, _) in glass {
+        positive(&format!("{} price", side), *price)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    const FIGI: &str = "BBG000B9XRY4";
+
+    fn trade(price: f32) -> Trade {
+        let now = Utc::now();
+        Trade {
+            price,
+            volume: 1,
+            figi: FIGI.to_string(),
+            interval: "1min".to_string(),
+            minute_rounded: now,
+            sent: now,
+            received: now,
+        }
+    }
+
+    fn order_book(bids: &[f32], asks: &[f32]) -> OrderBook {
+        let glass = |prices: &[f32]| prices.iter().map(|p| (*p, 1)).collect();
+        OrderBook {
+            figi: FIGI.to_string(),
+            depth: bids.len().max(asks.len()) as u32,
+            bids: glass(bids),
+            asks: glass(asks),
+            sent: Utc::now(),
+            received: Utc::now(),
+        }
+    }
+
+    fn candle(open: f32, close: f32, high: f32, low: f32) -> Candle {
+        let now = Utc::now();
+        Candle {
+            figi: FIGI.to_string(),
+            interval: "1min".to_string(),
+            open,
+            close,
+            high,
+            low,
+            volume: 1,
+            time: now,
+            sent: now,
+            received: now,
+        }
+    }
+
+    fn is_invalid<T: Validate>(item: &T) -> bool {
+        matches!(Validator::new(vec![]).check(item), Err(Rejection::InvalidPayload(_)))
+    }
+
+    #[test]
+    fn checks_figi() {
+        let validator = Validator::new(vec![FIGI.to_string()]);
+        assert_eq!(validator.check(&trade(100.0)), Ok(()));
+
+        let mut other = trade(100.0);
+        other.figi = "BBG000N9MNX3".to_string();
+        assert_eq!(
+            validator.check(&other),
+            Err(Rejection::UnknownFigi("BBG000N9MNX3".to_string()))
+        );
+        assert_eq!(Validator::new(vec!["*".to_string()]).check(&other), Ok(()));
+
+        other.figi = String::new();
+        assert_eq!(
+            validator.check(&other),
+            Err(Rejection::InvalidPayload("figi is empty".to_string()))
+        );
+    }
+
+    #[test]
+    fn trade_price_must_be_positive() {
+        assert!(!is_invalid(&trade(0.01)));
+        for price in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            assert!(is_invalid(&trade(price)), "price: {}", price);
+        }
+    }
+
+    #[test]
+    fn order_book_sides_are_sorted() {
+        assert!(!is_invalid(&order_book(&[100.0, 99.0, 98.0], &[101.0, 102.0, 103.0])));
+        // bids по возрастанию и повтор цены
+        assert!(is_invalid(&order_book(&[99.0, 100.0], &[101.0])));
+        assert!(is_invalid(&order_book(&[100.0, 100.0], &[101.0])));
+        // asks по убыванию и повтор цены
+        assert!(is_invalid(&order_book(&[100.0], &[102.0, 101.0])));
+        assert!(is_invalid(&order_book(&[100.0], &[101.0, 101.0])));
+    }
+
+    #[test]
+    fn order_book_is_not_crossed() {
+        assert!(is_invalid(&order_book(&[101.0], &[101.0])));
+        assert!(is_invalid(&order_book(&[102.0, 99.0], &[101.0, 103.0])));
+    }
+
+    #[test]
+    fn order_book_side_may_be_empty() {
+        assert!(!is_invalid(&order_book(&[], &[101.0, 102.0])));
+        assert!(!is_invalid(&order_book(&[100.0, 99.0], &[])));
+        assert!(!is_invalid(&order_book(&[], &[])));
+    }
+
+    #[test]
+    fn order_book_prices_must_be_positive() {
+        assert!(is_invalid(&order_book(&[100.0, 0.0], &[101.0])));
+        assert!(is_invalid(&order_book(&[100.0], &[101.0, f32::INFINITY])));
+        assert!(is_invalid(&order_book(&[f32::NAN], &[])));
+        assert!(is_invalid(&order_book(&[], &[-1.0])));
+    }
+
+    #[test]
+    fn candle_prices_are_consistent() {
+        assert!(!is_invalid(&candle(100.0, 101.0, 102.0, 99.0)));
+        assert!(!is_invalid(&candle(100.0, 100.0, 100.0, 100.0)));
+        assert!(is_invalid(&candle(100.0, 101.0, 99.0, 102.0)));
+        assert!(is_invalid(&candle(100.0, f32::NAN, 102.0, 99.0)));
+        assert!(is_invalid(&candle(0.0, 101.0, 102.0, 99.0)));
+        assert!(is_invalid(&candle(100.0, 101.0, 102.0, -1.0)));
+    }
+}
@@ -1,5 +1,7 @@
+use chrono::NaiveTime;
 use config::{Config, ConfigError, File};
 use serde::Deserialize;
+use shared::streaming::LagPolicy;
 
 const CONFIG_DEFAULT_FILE: &str = "default.yaml";
 const CONFIGS_DEFAULT_PATH: &str = "./configs/";
@@ -12,11 +14,129 @@ pub struct Log {
 #[derive(Debug, Deserialize, Clone)]
 pub struct Server {
     pub addr: String,
+    pub lag_policy: LagPolicy,
+    /// Инструменты, данные по которым принимаются в базу, пустой список или "*" - все инструменты
+    #[serde(default)]
+    pub figis: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct DB {
     pub url: String,
+    pub batch: Batch,
+    pub partitions: Partitions,
+    #[serde(default)]
+    pub on_startup: OnStartup,
+}
+
+/// Что делать с ранее сохраненными данными при запуске в режиме сохранения
+#[derive(Debug, Deserialize, Clone, PartialEq, Default)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum OnStartup {
+    /// Данные сохраняются, новые дописываются к ним
+    #[default]
+    Keep,
+    /// Удалить все данные за сегодня
+    DeleteToday,
+    /// Удалить данные за сегодня только от источника source (например, ipm)
+    DeleteSource { source: String },
+}
+
+impl OnStartup {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if let OnStartup::DeleteSource { source } = self {
+            if source.is_empty() {
+                return Err(ConfigError::Message(
+                    "source must be specified for delete_source policy".to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Пакетная вставка в режиме сохранения: сообщения копятся по типам и пишутся в базу одним запросом,
+/// когда набрано size сообщений или раз в flush_interval_ms
+#[derive(Debug, Deserialize, Clone)]
+pub struct Batch {
+    pub size: usize,
+    pub flush_interval_ms: u64,
+}
+
+impl Batch {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.size == 0 || self.flush_interval_ms == 0 {
+            return Err(ConfigError::Message(
+                "batch size and flush interval must be greater than 0".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Таблицы разбиты на партиции по дням (received, сутки по UTC). В режиме сохранения pr при запуске и затем раз в
+/// check_interval_secs создает партиции на premake_days дней вперед и применяет политику хранения (retention)
+#[derive(Debug, Deserialize, Clone)]
+pub struct Partitions {
+    pub premake_days: u32,
+    pub check_interval_secs: u64,
+    pub retention: Retention,
+}
+
+impl Partitions {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.check_interval_secs == 0 {
+            return Err(ConfigError::Message(
+                "partitions check interval must be greater than 0".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Партиции старше days дней удаляются или архивируются, days: 0 - хранить все
+#[derive(Debug, Deserialize, Clone)]
+pub struct Retention {
+    pub days: u32,
+    pub action: RetentionAction,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RetentionAction {
+    /// Удалить партицию вместе с данными
+    Drop,
+    /// Отсоединить партицию и перенести в схему archive (данные остаются в базе, но не участвуют в запросах)
+    Archive,
+}
+
+/// Биржа: часовой пояс (имя из базы часовых поясов, например Europe/Moscow) и торговая сессия по времени биржи.
+/// Календарные сутки при воспроизведении и очистке считаются в этом часовом поясе.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Exchange {
+    pub timezone: String,
+    pub session: Session,
+}
+
+impl Exchange {
+    fn validate(&self) -> Result<(), ConfigError> {
+        // Корректность имени проверяет база при первом запросе с часовым поясом
+        if self.timezone.is_empty() {
+            return Err(ConfigError::Message("exchange timezone must be specified".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+/// Начало и окончание сессии, если end не позже start, то сессия заканчивается на следующий день
+#[derive(Debug, Deserialize, Clone)]
+pub struct Session {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -25,12 +145,44 @@ pub enum Env {
     Testing,
 }
 
+/// Емкость каналов по типам данных
+#[derive(Debug, Deserialize, Clone)]
+pub struct Capacity {
+    pub trade: usize,
+    pub order_book: usize,
+    pub candle: usize,
+}
+
+/// fec (for external consumers) - broadcast каналы, через которые данные из базы транслируются наружу,
+/// fdc (for db consumer) - mpsc каналы, через которые данные поступают в базу
+#[derive(Debug, Deserialize, Clone)]
+pub struct Channels {
+    pub fec: Capacity,
+    pub fdc: Capacity,
+}
+
+impl Channels {
+    fn validate(&self) -> Result<(), ConfigError> {
+        for c in [&self.fec, &self.fdc].iter() {
+            if c.trade == 0 || c.order_book == 0 || c.candle == 0 {
+                return Err(ConfigError::Message(
+                    "channel capacity must be greater than 0".to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Settings {
     pub log: Log,
     pub env: Env,
     pub server: Server,
     pub db: DB,
+    pub exchange: Exchange,
+    pub channels: Channels,
 }
 
 // Пример отсюда: https://blog.logrocket.com/configuration-management-in-rust-web-services/
@@ -50,6 +202,13 @@ impl Settings {
         cfg.merge(File::with_name(&format!("{}{}", path, CONFIG_DEFAULT_FILE)))?;
         cfg.merge(File::with_name(&format!("{}{}", path, file_name)))?;
 
-        cfg.try_into()
+        let settings: Settings = cfg.try_into()?;
+        settings.channels.validate()?;
+        settings.db.batch.validate()?;
+        settings.db.partitions.validate()?;
+        settings.db.on_startup.validate()?;
+        settings.exchange.validate()?;
+
+        Ok(settings)
     }
 }
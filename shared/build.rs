@@ -0,0 +1,5 @@
+#[rustfmt::skip] // как в build.rs ipm и pr, иначе rustfmt соберет вызов в одну строку
+fn main() {
+    tonic_build::compile_protos("../proto/stats.proto")
+        .unwrap_or_else(|e| panic!("Failed to compile protos {:?}", e));
+}
@@ -0,0 +1,180 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::broadcast::{
+    self,
+    error::{RecvError, SendError},
+};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+/// Счетчики по каналу, нужны для того, чтобы подобрать емкость каналов (настройки channels),
+/// например, под всплеск активности на открытии торгов. Запрашиваются через grpc сервис Stats.
+#[derive(Debug, Clone)]
+pub struct ChannelStats {
+    name: &'static str,
+    capacity: usize,
+    counters: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    lagged: AtomicU64,
+}
+
+impl ChannelStats {
+    pub fn new(name: &'static str, capacity: usize) -> Self {
+        ChannelStats {
+            name,
+            capacity,
+            counters: Arc::new(Counters::default()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.name
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Успешно отправлено в канал
+    pub fn sent(&self) -> u64 {
+        self.counters.sent.load(Ordering::Relaxed)
+    }
+
+    /// Не отправлено: нет ни одного потребителя (broadcast) или канал переполнен (mpsc)
+    pub fn dropped(&self) -> u64 {
+        self.counters.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Потеряно потребителями, которые не успевали читать, суммарно по всем потребителям
+    pub fn lagged(&self) -> u64 {
+        self.counters.lagged.load(Ordering::Relaxed)
+    }
+
+    fn add(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+}
+
+/// mpsc канал со счетчиками, lagged для него не считается
+pub fn mpsc<T>(name: &'static str, capacity: usize) -> (MpscSender<T>, mpsc::Receiver<T>) {
+    let (sender, receiver) = mpsc::channel(capacity);
+    let sender = MpscSender {
+        sender,
+        stats: ChannelStats::new(name, capacity),
+    };
+    (sender, receiver)
+}
+
+#[derive(Debug)]
+pub struct MpscSender<T> {
+    sender: mpsc::Sender<T>,
+    stats: ChannelStats,
+}
+
+// derive(Clone) потребовал бы T: Clone
+impl<T> Clone for MpscSender<T> {
+    fn clone(&self) -> Self {
+        MpscSender {
+            sender: self.sender.clone(),
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+impl<T> MpscSender<T> {
+    /// Отправка с ожиданием свободного места в канале, dropped - только если канал закрыт
+    pub async fn send(&self, item: T) -> Result<(), mpsc::error::SendError<T>> {
+        let res = self.sender.send(item).await;
+        match res {
+            Ok(_) => ChannelStats::add(&self.stats.counters.sent, 1),
+            Err(_) => ChannelStats::add(&self.stats.counters.dropped, 1),
+        }
+        res
+    }
+
+    pub fn try_send(&self, item: T) -> Result<(), TrySendError<T>> {
+        let res = self.sender.try_send(item);
+        match res {
+            Ok(_) => ChannelStats::add(&self.stats.counters.sent, 1),
+            Err(_) => ChannelStats::add(&self.stats.counters.dropped, 1),
+        }
+        res
+    }
+
+    pub fn stats(&self) -> ChannelStats {
+        self.stats.clone()
+    }
+}
+
+/// broadcast канал со счетчиками
+pub fn broadcast<T: Clone>(name: &'static str, capacity: usize) -> BroadcastSender<T> {
+    let (sender, _) = broadcast::channel(capacity);
+    BroadcastSender {
+        sender,
+        stats: ChannelStats::new(name, capacity),
+    }
+}
+
+#[derive(Debug)]
+pub struct BroadcastSender<T> {
+    sender: broadcast::Sender<T>,
+    stats: ChannelStats,
+}
+
+// derive(Clone) потребовал бы T: Clone
+impl<T> Clone for BroadcastSender<T> {
+    fn clone(&self) -> Self {
+        BroadcastSender {
+            sender: self.sender.clone(),
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+impl<T: Clone> BroadcastSender<T> {
+    pub fn send(&self, item: T) -> Result<usize, SendError<T>> {
+        let res = self.sender.send(item);
+        match res {
+            Ok(_) => ChannelStats::add(&self.stats.counters.sent, 1),
+            Err(_) => ChannelStats::add(&self.stats.counters.dropped, 1),
+        }
+        res
+    }
+
+    pub fn subscribe(&self) -> Receiver<T> {
+        Receiver {
+            receiver: self.sender.subscribe(),
+            stats: self.stats.clone(),
+        }
+    }
+
+    pub fn receiver_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+
+    pub fn stats(&self) -> ChannelStats {
+        self.stats.clone()
+    }
+}
+
+/// Ресивер broadcast канала, учитывает потерянные сообщения (lagged)
+#[derive(Debug)]
+pub struct Receiver<T> {
+    receiver: broadcast::Receiver<T>,
+    stats: ChannelStats,
+}
+
+impl<T: Clone> Receiver<T> {
+    pub async fn recv(&mut self) -> Result<T, RecvError> {
+        let res = self.receiver.recv().await;
+        if let Err(RecvError::Lagged(n)) = res {
+            ChannelStats::add(&self.stats.counters.lagged, n);
+        }
+        res
+    }
+}
@@ -0,0 +1,24 @@
+use std::collections::HashSet;
+
+const WILDCARD: &str = "*";
+
+/// Фильтр по инструментам для grpc подписки, None - пропускаем все инструменты
+#[derive(Debug)]
+pub struct FigiFilter(Option<HashSet<String>>);
+
+impl FigiFilter {
+    pub fn new(figis: Vec<String>) -> Self {
+        if figis.is_empty() || figis.iter().any(|f| f == WILDCARD) {
+            return FigiFilter(None);
+        }
+
+        FigiFilter(Some(figis.into_iter().collect()))
+    }
+
+    pub fn is_match(&self, figi: &str) -> bool {
+        match &self.0 {
+            Some(figis) => figis.contains(figi),
+            None => true,
+        }
+    }
+}
@@ -0,0 +1,7 @@
+//! Общий код ipm и pr: каналы со счетчиками, grpc стримы для потребителей и сервис статистики
+
+pub mod channel;
+pub mod filter;
+pub mod receiver;
+pub mod statistics;
+pub mod streaming;
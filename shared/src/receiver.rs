@@ -0,0 +1,17 @@
+use crate::channel::{BroadcastSender, ChannelStats, Receiver};
+
+pub struct ReceiverMaker<T: Clone>(BroadcastSender<T>);
+
+impl<T: Clone> ReceiverMaker<T> {
+    pub fn new(sender: BroadcastSender<T>) -> Self {
+        Self(sender)
+    }
+
+    pub fn receiver(&self) -> Receiver<T> {
+        self.0.subscribe()
+    }
+
+    pub fn stats(&self) -> ChannelStats {
+        self.0.stats()
+    }
+}
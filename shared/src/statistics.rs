@@ -0,0 +1,38 @@
+use tonic::{Request, Response, Status};
+
+use stats::stats_server::Stats;
+use stats::{Channel, Channels, Empty};
+
+use crate::channel::ChannelStats;
+
+pub mod stats {
+    tonic::include_proto!("stats");
+}
+
+pub struct StatsService {
+    channels: Vec<ChannelStats>,
+}
+
+impl StatsService {
+    pub fn new(channels: Vec<ChannelStats>) -> Self {
+        StatsService { channels }
+    }
+}
+
+#[tonic::async_trait]
+impl Stats for StatsService {
+    async fn get_channels(&self, _request: Request<Empty>) -> Result<Response<Channels>, Status> {
+        let channels = self.channels.iter().map(convert_channel).collect();
+        Ok(Response::new(Channels { channels }))
+    }
+}
+
+fn convert_channel(stats: &ChannelStats) -> Channel {
+    Channel {
+        name: stats.name().to_string(),
+        capacity: stats.capacity() as u64,
+        sent: stats.sent(),
+        dropped: stats.dropped(),
+        lagged: stats.lagged(),
+    }
+}
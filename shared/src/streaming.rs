@@ -0,0 +1,69 @@
+use std::fmt::Debug;
+
+use futures::Stream;
+use log::{debug, info, warn};
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+use tonic::Status;
+
+use crate::channel::Receiver;
+use crate::filter::FigiFilter;
+
+/// Что делать, если потребитель grpc стрима не успевает вычитывать данные и часть сообщений теряется
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LagPolicy {
+    /// Отправить потребителю маркер пропуска с количеством потерянных сообщений и продолжить
+    Gap,
+    /// Отключить потребителя (поток завершается со статусом RESOURCE_EXHAUSTED)
+    Disconnect,
+}
+
+/// Сообщение grpc стрима, которое может быть маркером пропуска (см. incoming.proto)
+pub trait GapMarker {
+    fn gap(dropped: u64) -> Self;
+}
+
+/// Стрим для потребителя: данные из broadcast ресивера, отфильтрованные по инструментам.
+/// Если потребитель отстал (broadcast канал переполнен), то поступаем согласно policy,
+/// если источник данных закрылся, то стрим завершается со статусом UNAVAILABLE.
+pub fn stream<D, P>(
+    name: &'static str,
+    mut receiver: Receiver<D>,
+    filter: FigiFilter,
+    figi: fn(&D) -> &str,
+    policy: LagPolicy,
+) -> impl Stream<Item = Result<P, Status>>
+where
+    D: Debug + Clone + Send + 'static,
+    P: From<D> + GapMarker + Send + 'static,
+{
+    async_stream::try_stream! {
+        loop {
+            match receiver.recv().await {
+                Ok(item) => {
+                    if !filter.is_match(figi(&item)) {
+                        continue;
+                    }
+
+                    debug!("grpc input: {:?}", item);
+                    yield P::from(item);
+                }
+                Err(RecvError::Lagged(dropped)) => {
+                    warn!("{} consumer is lagging, dropped {} messages, policy: {:?}", name, dropped, policy);
+                    match policy {
+                        LagPolicy::Gap => yield P::gap(dropped),
+                        LagPolicy::Disconnect => Err(Status::resource_exhausted(format!(
+                            "consumer is too slow, dropped {} {} messages",
+                            dropped, name
+                        )))?,
+                    }
+                }
+                Err(RecvError::Closed) => {
+                    info!("{} source closed, finishing stream", name);
+                    Err(Status::unavailable(format!("{} source closed", name)))?;
+                }
+            }
+        }
+    }
+}
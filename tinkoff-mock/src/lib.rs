@@ -0,0 +1,361 @@
+//! Mock ws сервер, который разговаривает по протоколу Tinkoff market data
+//! (https://tinkoffcreditsystems.github.io/invest-openapi/marketdata/).
+//! Принимает подписки candle:subscribe и orderbook:subscribe, отправляет по ним события candle и orderbook,
+//! на некорректные запросы отвечает error событиями. Поведение (разрывы соединения, мусорные фреймы, "зависание")
+//! задается сценарием (script::Script). Используется в интеграционных тестах ipm и как отдельный бинарник.
+
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+use futures::stream::{SplitSink, SplitStream};
+use futures::{SinkExt, StreamExt};
+use log::{debug, info, warn};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Notify};
+use tokio::time::{self, Duration};
+use tokio_tungstenite::{accept_hdr_async, WebSocketStream};
+use tokio_util::sync::CancellationToken;
+use tungstenite::handshake::server::{Callback, ErrorResponse, Request as HsRequest, Response as HsResponse};
+use tungstenite::http::StatusCode;
+use tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
+use tungstenite::Message;
+
+use protocol::{Action, Request, Subscription};
+use script::{Script, Step};
+
+pub mod protocol;
+pub mod script;
+
+type RequestLog = Arc<Mutex<Vec<Vec<Request>>>>;
+
+/// Сервер работает, пока жив MockServer, при удалении все подключения закрываются
+pub struct MockServer {
+    addr: SocketAddr,
+    requests: RequestLog,
+    shutdown: CancellationToken,
+}
+
+impl MockServer {
+    /// addr - например, "127.0.0.1:0", тогда порт будет выбран свободный
+    pub async fn start(addr: &str, script: Script) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let requests = RequestLog::default();
+        let shutdown = CancellationToken::new();
+
+        info!("tinkoff mock server listening on: {}", addr);
+        tokio::spawn(accepting(
+            listener,
+            Arc::new(script),
+            requests.clone(),
+            shutdown.clone(),
+        ));
+
+        Ok(MockServer {
+            addr,
+            requests,
+            shutdown,
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn url(&self) -> String {
+        format!("ws://{}", self.addr)
+    }
+
+    /// Количество принятых подключений (отклоненные при авторизации не считаются)
+    pub fn connections(&self) -> usize {
+        self.requests.lock().unwrap().len()
+    }
+
+    /// Запросы, полученные в рамках подключения с указанным номером (нумерация с 0)
+    pub fn requests(&self, connection: usize) -> Vec<Request> {
+        self.requests
+            .lock()
+            .unwrap()
+            .get(connection)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Ждет, пока в рамках подключения не наберется count запросов, ограничение по времени - на вызывающей стороне
+    pub async fn wait_requests(&self, connection: usize, count: usize) -> Vec<Request> {
+        loop {
+            let requests = self.requests(connection);
+            if requests.len() >= count {
+                return requests;
+            }
+            time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    pub async fn wait_connections(&self, count: usize) {
+        while self.connections() < count {
+            time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    pub fn stop(&self) {
+        self.shutdown.cancel();
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+async fn accepting(listener: TcpListener, script: Arc<Script>, requests: RequestLog, shutdown: CancellationToken) {
+    loop {
+        tokio::select! {
+            res = listener.accept() => match res {
+                Ok((stream, peer)) => {
+                    debug!("new tcp connection from {}", peer);
+                    tokio::spawn(handle_connection(stream, script.clone(), requests.clone(), shutdown.clone()));
+                },
+                Err(err) => warn!("accepting connection failed, error: {}", err),
+            },
+            _ = shutdown.cancelled() => {
+                info!("tinkoff mock server finished");
+                return;
+            }
+        }
+    }
+}
+
+type WebSocket = WebSocketStream<TcpStream>;
+
+/// Состояние одного подключения, общее для читающей задачи и исполнителя сценария
+#[derive(Default)]
+struct Connection {
+    subscriptions: Mutex<BTreeSet<Subscription>>,
+    subscribed: AtomicUsize,
+    notify: Notify,
+    silence: CancellationToken,
+    disconnected: CancellationToken,
+}
+
+/// Проверка токена при подключении, если токен в сценарии не задан, то принимаются все подключения
+struct TokenCheck(Option<String>);
+
+impl Callback for TokenCheck {
+    fn on_request(self, req: &HsRequest, resp: HsResponse) -> Result<HsResponse, ErrorResponse> {
+        let token = match self.0 {
+            Some(token) => token,
+            None => return Ok(resp),
+        };
+
+        let authorization = req.headers().get("Authorization").and_then(|v| v.to_str().ok());
+        match authorization == Some(format!("Bearer {}", token).as_str()) {
+            true => Ok(resp),
+            false => {
+                let mut resp = ErrorResponse::new(Some("Unauthorized".to_string()));
+                *resp.status_mut() = StatusCode::UNAUTHORIZED;
+                Err(resp)
+            }
+        }
+    }
+}
+
+/// Чем закончилось выполнение сценария
+enum Finish {
+    Done,
+    Close,
+    Drop,
+    Disconnected,
+}
+
+async fn handle_connection(stream: TcpStream, script: Arc<Script>, requests: RequestLog, shutdown: CancellationToken) {
+    let ws = match accept_hdr_async(stream, TokenCheck(script.token.clone())).await {
+        Ok(ws) => ws,
+        Err(err) => {
+            warn!("ws handshake failed, error: {}", err);
+            return;
+        }
+    };
+
+    let number = {
+        let mut requests = requests.lock().unwrap();
+        requests.push(Vec::new());
+        requests.len() - 1
+    };
+    info!("connection {} accepted", number);
+
+    let (sink, stream) = ws.split();
+    let (sender, receiver) = mpsc::unbounded_channel::<Message>();
+    let conn = Arc::new(Connection::default());
+
+    let mut writer = tokio::spawn(writing(sink, receiver));
+    let reader = tokio::spawn(reading(stream, number, sender.clone(), conn.clone(), requests));
+
+    let finish = tokio::select! {
+        finish = run_steps(script.steps(number), sender, conn.clone()) => finish,
+        _ = conn.disconnected.cancelled() => Finish::Disconnected,
+        _ = shutdown.cancelled() => Finish::Drop,
+    };
+
+    match finish {
+        // Сценарий закончился, держим соединение, пока клиент сам не отключится
+        Finish::Done => {
+            tokio::select! {
+                _ = conn.disconnected.cancelled() => {},
+                _ = shutdown.cancelled() => {},
+            }
+        }
+        // writer допишет очередь (включая close фрейм) и завершится
+        Finish::Close => {
+            let _ = time::timeout(Duration::from_secs(1), &mut writer).await;
+        }
+        Finish::Drop | Finish::Disconnected => {}
+    }
+
+    // Удаление обеих половин сокета закрывает TCP соединение
+    writer.abort();
+    reader.abort();
+    info!("connection {} finished", number);
+}
+
+async fn writing(mut sink: SplitSink<WebSocket, Message>, mut receiver: mpsc::UnboundedReceiver<Message>) {
+    while let Some(msg) = receiver.recv().await {
+        let close = msg.is_close();
+        if let Err(err) = sink.send(msg).await {
+            debug!("sending to client failed, error: {}", err);
+            return;
+        }
+
+        if close {
+            return;
+        }
+    }
+}
+
+async fn reading(
+    mut stream: SplitStream<WebSocket>,
+    number: usize,
+    sender: mpsc::UnboundedSender<Message>,
+    conn: Arc<Connection>,
+    requests: RequestLog,
+) {
+    loop {
+        let msg = tokio::select! {
+            msg = stream.next() => msg,
+            // Перестаем читать, тогда и pong на ping клиента не отправляется
+            _ = conn.silence.cancelled() => {
+                futures::future::pending::<()>().await;
+                return;
+            }
+        };
+
+        match msg {
+            Some(Ok(Message::Text(text))) => {
+                debug!("connection {} request: {}", number, text);
+                let req = match Request::parse(&text) {
+                    Ok(req) => req,
+                    Err(err) => {
+                        let _ = sender.send(Message::Text(protocol::error_event(&err, None, Utc::now())));
+                        continue;
+                    }
+                };
+
+                requests.lock().unwrap()[number].push(req.clone());
+                if let Err(err) = apply_request(&req, &conn) {
+                    let event = protocol::error_event(&err, req.request_id.as_deref(), Utc::now());
+                    let _ = sender.send(Message::Text(event));
+                }
+            }
+            Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+            Some(Ok(_)) => {}
+        }
+    }
+
+    conn.disconnected.cancel();
+}
+
+fn apply_request(req: &Request, conn: &Connection) -> Result<(), String> {
+    match req.action()? {
+        Action::Subscribe(subscription) => {
+            conn.subscriptions.lock().unwrap().insert(subscription);
+            conn.subscribed.fetch_add(1, Ordering::SeqCst);
+            conn.notify.notify_one();
+            Ok(())
+        }
+        Action::Unsubscribe(subscription) => match conn.subscriptions.lock().unwrap().remove(&subscription) {
+            true => Ok(()),
+            false => Err("Subscription not found".to_string()),
+        },
+    }
+}
+
+async fn run_steps(steps: Vec<Step>, sender: mpsc::UnboundedSender<Message>, conn: Arc<Connection>) -> Finish {
+    let mut n = 0;
+    let send = |msg: String| sender.send(Message::Text(msg)).is_ok();
+
+    for step in steps {
+        debug!("step: {:?}", step);
+        match step {
+            Step::WaitSubscriptions { count } => {
+                while conn.subscribed.load(Ordering::SeqCst) < count {
+                    conn.notify.notified().await;
+                }
+            }
+            Step::Events { count, interval_ms } => {
+                for _ in 0..count {
+                    if !send_events(&send, &conn, &mut n) {
+                        return Finish::Disconnected;
+                    }
+                    time::sleep(Duration::from_millis(interval_ms)).await;
+                }
+            }
+            Step::Stream { interval_ms } => loop {
+                if !send_events(&send, &conn, &mut n) {
+                    return Finish::Disconnected;
+                }
+                time::sleep(Duration::from_millis(interval_ms)).await;
+            },
+            Step::Raw { text } => {
+                if !send(text) {
+                    return Finish::Disconnected;
+                }
+            }
+            Step::Error { message } => {
+                if !send(protocol::error_event(&message, None, Utc::now())) {
+                    return Finish::Disconnected;
+                }
+            }
+            Step::Sleep { ms } => time::sleep(Duration::from_millis(ms)).await,
+            Step::Close { code, reason } => {
+                let frame = CloseFrame {
+                    code: CloseCode::from(code),
+                    reason: reason.into(),
+                };
+                let _ = sender.send(Message::Close(Some(frame)));
+                return Finish::Close;
+            }
+            Step::Drop => return Finish::Drop,
+            Step::Silence => {
+                conn.silence.cancel();
+                futures::future::pending::<()>().await;
+            }
+        }
+    }
+
+    Finish::Done
+}
+
+fn send_events(send: &impl Fn(String) -> bool, conn: &Connection, n: &mut u64) -> bool {
+    let subscriptions: Vec<_> = conn.subscriptions.lock().unwrap().iter().cloned().collect();
+    for subscription in subscriptions.iter() {
+        if !send(protocol::event(subscription, *n, Utc::now())) {
+            return false;
+        }
+        *n += 1;
+    }
+    true
+}
@@ -0,0 +1,71 @@
+use std::fs;
+
+use clap::{App, Arg};
+use flexi_logger::Logger;
+use log::info;
+use tokio_util::sync::CancellationToken;
+
+use tinkoff_mock::{script::Script, MockServer};
+
+const ADDR: &str = "addr";
+const SCRIPT: &str = "script";
+const LOG_LEVEL: &str = "log_level";
+
+/// Отдельный запуск mock сервера, например, чтобы погонять ipm без токена:
+/// в конфиге ipm указать ws: ws://127.0.0.1:8080 и запустить сервер со сценарием из scripts
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let am = App::new("tinkoff mock server")
+        .version("0.1.0")
+        .about("mock of tinkoff investments market data streaming server")
+        .arg(
+            Arg::new(ADDR)
+                .short('a')
+                .long(ADDR)
+                .value_name("ADDR")
+                .default_value("127.0.0.1:8080")
+                .about("sets an address to listen on"),
+        )
+        .arg(
+            Arg::new(SCRIPT)
+                .short('s')
+                .long(SCRIPT)
+                .value_name("PATH TO SCRIPT")
+                .about("sets a yaml script, by default events are streamed every second"),
+        )
+        .arg(
+            Arg::new(LOG_LEVEL)
+                .short('l')
+                .long(LOG_LEVEL)
+                .value_name("LEVEL")
+                .default_value("info")
+                .about("sets a log level"),
+        )
+        .get_matches();
+
+    Logger::try_with_str(am.value_of(LOG_LEVEL).unwrap_or("info"))?
+        .format(flexi_logger::colored_detailed_format)
+        .start()?;
+
+    let script = match am.value_of(SCRIPT) {
+        Some(path) => Script::from_yaml(&fs::read_to_string(path)?)?,
+        None => Script::default(),
+    };
+
+    let shutdown = run_ctrlc()?;
+    let _server = MockServer::start(am.value_of(ADDR).unwrap_or("127.0.0.1:8080"), script).await?;
+
+    shutdown.cancelled().await;
+    info!("tinkoff mock server stopped");
+
+    Ok(())
+}
+
+fn run_ctrlc() -> anyhow::Result<CancellationToken> {
+    let token = CancellationToken::new();
+    let t = token.clone();
+    ctrlc::set_handler(move || {
+        t.cancel();
+    })?;
+    Ok(token)
+}
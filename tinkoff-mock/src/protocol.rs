@@ -0,0 +1,126 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+// https://tinkoffcreditsystems.github.io/invest-openapi/marketdata/
+const CANDLE_INTERVALS: [&str; 13] = [
+    "1min", "2min", "3min", "5min", "10min", "15min", "30min", "hour", "2hour", "4hour", "day", "week", "month",
+];
+const ORDER_BOOK_MAX_DEPTH: u32 = 20;
+
+/// Запрос клиента на подписку или отписку, в таком виде он сохраняется для проверки в тестах
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Request {
+    pub event: String,
+    pub figi: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub depth: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+/// Активная подписка в рамках одного подключения
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Subscription {
+    Candle { figi: String, interval: String },
+    OrderBook { figi: String, depth: u32 },
+}
+
+pub enum Action {
+    Subscribe(Subscription),
+    Unsubscribe(Subscription),
+}
+
+impl Request {
+    /// Разбор запроса, ошибка - текст, который нужно отправить клиенту в error событии
+    pub fn parse(text: &str) -> Result<Request, String> {
+        serde_json::from_str(text).map_err(|err| format!("Invalid request: {}", err))
+    }
+
+    pub fn action(&self) -> Result<Action, String> {
+        let subscription = match self.event.split(':').next() {
+            Some("candle") => {
+                let interval = self.interval.clone().ok_or("Field interval is required")?;
+                if !CANDLE_INTERVALS.contains(&interval.as_str()) {
+                    return Err(format!("Unknown interval: {}", interval));
+                }
+                Subscription::Candle {
+                    figi: self.figi.clone(),
+                    interval,
+                }
+            }
+            Some("orderbook") => {
+                let depth = self.depth.ok_or("Field depth is required")?;
+                if depth == 0 || depth > ORDER_BOOK_MAX_DEPTH {
+                    return Err(format!("Depth must be in range 1..={}", ORDER_BOOK_MAX_DEPTH));
+                }
+                Subscription::OrderBook {
+                    figi: self.figi.clone(),
+                    depth,
+                }
+            }
+            _ => return Err(format!("Unknown event: {}", self.event)),
+        };
+
+        match self.event.split(':').nth(1) {
+            Some("subscribe") => Ok(Action::Subscribe(subscription)),
+            Some("unsubscribe") => Ok(Action::Unsubscribe(subscription)),
+            _ => Err(format!("Unknown event: {}", self.event)),
+        }
+    }
+}
+
+/// Событие с данными по подписке, n - порядковый номер, от него зависят цены (чтобы данные были разными)
+pub fn event(subscription: &Subscription, n: u64, time: DateTime<Utc>) -> String {
+    let price = 100.0 + (n % 100) as f64 / 100.0;
+    let value = match subscription {
+        Subscription::Candle { figi, interval } => json!({
+            "event": "candle",
+            "time": time,
+            "payload": {
+                "o": price,
+                "c": price + 0.01,
+                "h": price + 0.02,
+                "l": price - 0.01,
+                "v": n + 1,
+                "time": time,
+                "interval": interval,
+                "figi": figi,
+            }
+        }),
+        Subscription::OrderBook { figi, depth } => {
+            let levels = |sign: f64| -> Vec<(f64, u64)> {
+                (0..*depth)
+                    .map(|i| (price + sign * 0.01 * (i + 1) as f64, (i + 1) as u64))
+                    .collect()
+            };
+            json!({
+                "event": "orderbook",
+                "time": time,
+                "payload": {
+                    "figi": figi,
+                    "depth": depth,
+                    "bids": levels(-1.0),
+                    "asks": levels(1.0),
+                }
+            })
+        }
+    };
+
+    value.to_string()
+}
+
+/// Так Тинькофф сообщает об ошибке в запросе
+pub fn error_event(error: &str, request_id: Option<&str>, time: DateTime<Utc>) -> String {
+    json!({
+        "event": "error",
+        "time": time,
+        "payload": {
+            "error": error,
+            "request_id": request_id,
+        }
+    })
+    .to_string()
+}
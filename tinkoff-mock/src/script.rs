@@ -0,0 +1,64 @@
+use serde::Deserialize;
+
+/// Сценарий работы mock сервера. Для каждого подключения по порядку берется свой список шагов,
+/// если подключений больше, чем списков, то для остальных используется последний.
+/// Пустой сценарий - бесконечная трансляция данных по подпискам раз в секунду.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Script {
+    /// Если задан, то подключения с другим токеном отклоняются с HTTP 401
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub connections: Vec<Vec<Step>>,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum Step {
+    /// Ждать, пока в рамках подключения не придет count запросов на подписку
+    WaitSubscriptions {
+        count: usize,
+    },
+    /// count раз отправить события по всем активным подпискам с паузой interval_ms
+    Events {
+        count: u64,
+        interval_ms: u64,
+    },
+    /// То же, что Events, но бесконечно
+    Stream {
+        interval_ms: u64,
+    },
+    /// Отправить произвольный текстовый фрейм (например, мусор или json неизвестного формата)
+    Raw {
+        text: String,
+    },
+    /// Отправить error событие
+    Error {
+        message: String,
+    },
+    Sleep {
+        ms: u64,
+    },
+    /// Закрыть соединение с close фреймом
+    Close {
+        code: u16,
+        reason: String,
+    },
+    /// Разорвать TCP соединение без close фрейма
+    Drop,
+    /// Соединение открыто, но сервер перестает отвечать, в том числе на ping (эмуляция полуоткрытого соединения)
+    Silence,
+}
+
+impl Script {
+    pub fn steps(&self, connection: usize) -> Vec<Step> {
+        match self.connections.get(connection).or_else(|| self.connections.last()) {
+            Some(steps) => steps.clone(),
+            None => vec![Step::Stream { interval_ms: 1000 }],
+        }
+    }
+
+    pub fn from_yaml(text: &str) -> anyhow::Result<Self> {
+        Ok(serde_yaml::from_str(text)?)
+    }
+}